@@ -24,3 +24,385 @@ pub async fn call_canister<T: CandidType + Send, R: CandidType + for<'de> Deseri
             message: err.to_string(),
         })
 }
+
+/// 分页调用罐子，依次请求每一页直到取完所有数据
+///
+/// * `page` 为第一页的分页参数，之后每次页码加一
+/// * `args` 根据分页参数构造调用参数，接口参数通常为 `(QueryPage,)`
+/// * `max_calls` 最多调用次数，超过后返回错误
+#[cfg(feature = "common")]
+pub async fn call_canister_all_pages<T, R, F>(
+    canister_id: crate::identity::CanisterId,
+    method: &str,
+    page: crate::common::pages::QueryPage,
+    max_calls: u32,
+    args: F,
+) -> super::types::CanisterCallResult<Vec<R>>
+where
+    T: CandidType + Send,
+    R: CandidType + for<'de> Deserialize<'de>,
+    F: Fn(&crate::common::pages::QueryPage) -> T + Send + Sync,
+{
+    let items = fetch_all_pages(page, max_calls, |page| {
+        call_canister::<T, crate::common::pages::PageData<R>>(canister_id, method, args(page))
+    })
+    .await?;
+    items.ok_or_else(|| {
+        super::types::CanisterCallError::new(
+            canister_id,
+            method,
+            format!("too many calls: max_calls({max_calls}) reached before all pages were fetched"),
+        )
+    })
+}
+
+// 依次请求每一页，超过最多调用次数返回 None
+#[cfg(feature = "common")]
+async fn fetch_all_pages<R, E, Fut>(
+    page: crate::common::pages::QueryPage,
+    max_calls: u32,
+    fetch: impl Fn(&crate::common::pages::QueryPage) -> Fut,
+) -> Result<Option<Vec<R>>, E>
+where
+    Fut: Future<Output = Result<crate::common::pages::PageData<R>, E>>,
+{
+    // 起始页之前的数据不会被请求
+    let skipped = page.page.saturating_sub(1).saturating_mul(page.size as u64);
+    let mut page = page;
+    let mut items = Vec::new();
+    for _ in 0..max_calls {
+        let data = fetch(&page).await?;
+        let fetched = data.data.len();
+        items.extend(data.data);
+        // 取完所有数据，或者远程数据减少导致本页不满
+        if data.total <= skipped.saturating_add(items.len() as u64) || fetched == 0 || fetched < page.size as usize {
+            return Ok(Some(items));
+        }
+        page.page += 1;
+    }
+    Ok(None)
+}
+
+// ========================= 调用选项 =========================
+
+/*
+
+有界等待并在 SysTransient 时立即重试，带幂等键的调用在 SysUnknown (超时) 时也会重试
+
+重试不会等待，调用期间不能用定时器等待，否则调用任务会被取消。
+需要等待一段时间再重试的，把错误返回给调用方，再通过 TaskQueue 等定时重新调度
+
+let options = CallOptions { timeout_seconds: Some(60), max_attempts: 3, ..Default::default() };
+let key = idempotency_key(with_mut_state(|s| s.next_nonce()));
+let result: Result<(), String> = call_canister_idempotent(ledger, "transfer", key, arg, &options).await?;
+
+被调用方按 (调用者, 幂等键) 记录已经处理过的请求，重复的请求直接返回之前的结果。
+幂等键的格式是可以预测的，必须和 msg_caller 绑定，否则其他身份可以抢先占用别人的幂等键
+
+#[ic_cdk::update]
+fn transfer(arg: IdempotentArg<TransferArg>) -> Result<(), String> {
+    let caller = ic_canister_kit::identity::caller();
+    if let Some(result) = with_state(|s| s.idempotency.find(&caller, &arg.idempotency_key).cloned()) {
+        return result;
+    }
+    let result = do_transfer(arg.arg);
+    with_mut_state(|s| s.idempotency.insert(caller, arg.idempotency_key, result.clone()));
+    result
+}
+
+! 超时 (SysUnknown) 重试时，原来的消息可能仍在执行。如果接口在查询和记录之间有 await，
+! 重试的请求可能与原请求并发执行，两者都查不到记录。这种接口需要在 await 之前先记录一个处理中的标记
+
+*/
+
+/// 调用选项
+#[derive(Debug, Clone)]
+pub struct CallOptions {
+    /// 有界等待的超时秒数，None 表示无界等待
+    pub timeout_seconds: Option<u32>,
+    /// 附带的 cycles
+    pub cycles: u128,
+    /// 最多尝试次数，0 和 1 都表示不重试，失败后立即重试
+    pub max_attempts: u32,
+}
+
+impl Default for CallOptions {
+    fn default() -> Self {
+        Self {
+            timeout_seconds: None,
+            cycles: 0,
+            max_attempts: 1,
+        }
+    }
+}
+
+// 只有 SysTransient 一定没有执行，SysUnknown 可能已经执行，只有幂等调用才能重试
+fn should_retry(err: &ic_cdk::call::CallFailed, idempotent: bool) -> bool {
+    use ic_cdk::call::RejectCode;
+    let ic_cdk::call::CallFailed::CallRejected(rejected) = err else {
+        return false;
+    };
+    match rejected.reject_code() {
+        Ok(RejectCode::SysTransient) => true,
+        Ok(RejectCode::SysUnknown) => idempotent,
+        _ => false,
+    }
+}
+
+// 失败后立即重试，最多尝试 max_attempts 次
+async fn retry_call<T, F, Fut>(max_attempts: u32, idempotent: bool, mut call: F) -> Result<T, ic_cdk::call::CallFailed>
+where
+    T: Send,
+    F: FnMut(u32) -> Fut + Send,
+    Fut: Future<Output = Result<T, ic_cdk::call::CallFailed>> + Send,
+{
+    let mut attempt = 0;
+    loop {
+        attempt += 1;
+        match call(attempt).await {
+            Err(err) if attempt < max_attempts && should_retry(&err, idempotent) => {}
+            result => return result,
+        }
+    }
+}
+
+async fn call_with_retry<R: CandidType + for<'de> Deserialize<'de>>(
+    canister_id: crate::identity::CanisterId,
+    method: &str,
+    args: Vec<u8>,
+    options: &CallOptions,
+    idempotent: bool,
+) -> super::types::CanisterCallResult<R> {
+    let call_result = retry_call(options.max_attempts, idempotent, |attempt| {
+        ic_cdk::println!("call canister: {} -> {} ({attempt})", canister_id.to_text(), method);
+        let call = match options.timeout_seconds {
+            Some(timeout_seconds) => {
+                ic_cdk::call::Call::bounded_wait(canister_id, method).change_timeout(timeout_seconds)
+            }
+            None => ic_cdk::call::Call::unbounded_wait(canister_id, method),
+        };
+        call.with_raw_args(&args).with_cycles(options.cycles).into_future()
+    })
+    .await;
+    super::fetch_and_wrap_call_result(canister_id, method, call_result)
+}
+
+/// 按选项调用罐子，SysTransient 错误会立即重试
+pub async fn call_canister_with_options<T: CandidType + Send, R: CandidType + for<'de> Deserialize<'de>>(
+    canister_id: crate::identity::CanisterId,
+    method: &str,
+    args: T,
+    options: &CallOptions,
+) -> super::types::CanisterCallResult<R> {
+    let args =
+        candid::encode_one(args).map_err(|err| super::types::CanisterCallError::new(canister_id, method, err))?;
+    call_with_retry(canister_id, method, args, options, false).await
+}
+
+// ========================= 幂等调用 =========================
+
+/// 幂等键
+pub type IdempotencyKey = String;
+
+/// 生成幂等键，nonce 需要由调用方持久化并保证不重复
+pub fn idempotency_key(nonce: u64) -> IdempotencyKey {
+    format!("{}:{nonce}", crate::identity::self_canister_id().to_text())
+}
+
+/// 带幂等键的参数
+#[derive(CandidType, serde::Serialize, Deserialize, Debug, Clone)]
+pub struct IdempotentArg<T> {
+    /// 幂等键，相同的键只会处理一次
+    pub idempotency_key: IdempotencyKey,
+    /// 参数
+    pub arg: T,
+}
+
+/// 带幂等键调用罐子，SysTransient 和 SysUnknown 错误都会立即重试
+/// ! 被调用的接口参数必须是 IdempotentArg，并且对相同的幂等键返回相同的结果
+pub async fn call_canister_idempotent<T: CandidType + Send, R: CandidType + for<'de> Deserialize<'de>>(
+    canister_id: crate::identity::CanisterId,
+    method: &str,
+    idempotency_key: IdempotencyKey,
+    arg: T,
+    options: &CallOptions,
+) -> super::types::CanisterCallResult<R> {
+    let args = candid::encode_one(IdempotentArg { idempotency_key, arg })
+        .map_err(|err| super::types::CanisterCallError::new(canister_id, method, err))?;
+    call_with_retry(canister_id, method, args, options, true).await
+}
+
+/// 被调用方记录已经处理的请求及结果，按 (调用者, 幂等键) 区分
+#[cfg(feature = "times")]
+#[derive(CandidType, serde::Serialize, Deserialize, Debug, Clone)]
+pub struct IdempotencyCache<R> {
+    /// 保留时间
+    pub ttl: crate::times::DurationNanos,
+    /// 处理时间及结果
+    pub results:
+        std::collections::BTreeMap<(crate::identity::CallerId, IdempotencyKey), (crate::times::TimestampNanos, R)>,
+}
+
+#[cfg(feature = "times")]
+impl<R> Default for IdempotencyCache<R> {
+    fn default() -> Self {
+        Self {
+            ttl: crate::times::DurationNanos::from(24 * 3600 * 1_000_000_000), // 默认保留 1 天
+            results: Default::default(),
+        }
+    }
+}
+
+#[cfg(feature = "times")]
+impl<R> IdempotencyCache<R> {
+    /// 查询调用者已经处理的结果
+    pub fn find(&self, caller: &crate::identity::CallerId, idempotency_key: &str) -> Option<&R> {
+        self.find_at(caller, idempotency_key, crate::times::now())
+    }
+
+    /// 记录调用者的处理结果，并清除过期的记录
+    pub fn insert(&mut self, caller: crate::identity::CallerId, idempotency_key: IdempotencyKey, result: R) {
+        self.insert_at(caller, idempotency_key, result, crate::times::now());
+    }
+
+    fn expired(&self, time: crate::times::TimestampNanos, now: crate::times::TimestampNanos) -> bool {
+        let elapsed = now.into_inner().saturating_sub(time.into_inner()).max(0) as u128;
+        self.ttl.into_inner() <= elapsed
+    }
+
+    fn find_at(
+        &self,
+        caller: &crate::identity::CallerId,
+        idempotency_key: &str,
+        now: crate::times::TimestampNanos,
+    ) -> Option<&R> {
+        self.results
+            .get(&(*caller, idempotency_key.to_string()))
+            .filter(|(time, _)| !self.expired(*time, now))
+            .map(|(_, result)| result)
+    }
+
+    fn insert_at(
+        &mut self,
+        caller: crate::identity::CallerId,
+        idempotency_key: IdempotencyKey,
+        result: R,
+        now: crate::times::TimestampNanos,
+    ) {
+        let expired: Vec<_> = self
+            .results
+            .iter()
+            .filter(|(_, (time, _))| self.expired(*time, now))
+            .map(|(key, _)| key.clone())
+            .collect();
+        for key in expired {
+            self.results.remove(&key);
+        }
+        self.results.insert((caller, idempotency_key), (now, result));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ic_cdk::call::{CallFailed, CallRejected, RejectCode};
+
+    #[cfg(feature = "times")]
+    use super::IdempotencyCache;
+    use super::{CallOptions, should_retry};
+
+    #[test]
+    fn retries_transient_errors_immediately() {
+        let rejected =
+            |code: RejectCode| CallFailed::CallRejected(CallRejected::with_rejection(code as u32, "".into()));
+        assert!(should_retry(&rejected(RejectCode::SysTransient), false));
+        assert!(!should_retry(&rejected(RejectCode::SysUnknown), false));
+        assert!(should_retry(&rejected(RejectCode::SysUnknown), true));
+        assert!(!should_retry(&rejected(RejectCode::CanisterError), true));
+
+        // 前 failures 次返回 code 错误，之后成功，返回结果和实际尝试次数
+        let run = |max_attempts: u32, idempotent: bool, failures: u32, code: RejectCode| {
+            let mut attempts = 0;
+            let result = {
+                let future = super::retry_call(max_attempts, idempotent, |attempt| {
+                    attempts = attempt;
+                    std::future::ready(if attempt <= failures {
+                        Err(rejected(code))
+                    } else {
+                        Ok(attempt)
+                    })
+                });
+                let mut future = std::pin::pin!(future);
+                let mut cx = std::task::Context::from_waker(std::task::Waker::noop());
+                let std::task::Poll::Ready(result) = future.as_mut().poll(&mut cx) else {
+                    unreachable!()
+                };
+                result.ok()
+            };
+            (result, attempts)
+        };
+
+        assert_eq!(run(3, false, 2, RejectCode::SysTransient), (Some(3), 3));
+        assert_eq!(run(2, false, 2, RejectCode::SysTransient), (None, 2));
+        assert_eq!(run(3, false, 1, RejectCode::SysUnknown), (None, 1));
+        assert_eq!(run(3, true, 1, RejectCode::SysUnknown), (Some(2), 2));
+        assert_eq!(run(3, true, 1, RejectCode::CanisterError), (None, 1));
+        assert_eq!(
+            run(CallOptions::default().max_attempts, false, 1, RejectCode::SysTransient),
+            (None, 1)
+        );
+    }
+
+    #[cfg(feature = "common")]
+    #[test]
+    fn fetches_remaining_pages_from_a_later_start_page() {
+        use crate::common::pages::{PageData, QueryPage};
+
+        // 从第 page 页开始，每页 10 条，远程共有 total 条
+        let run = |total: u32, page: u64, max_calls: u32| {
+            let source: Vec<u32> = (0..total).collect();
+            let fetch = |page: &QueryPage| {
+                let start = ((page.page - 1) * page.size as u64) as usize;
+                let data = source.iter().skip(start).take(page.size as usize).copied().collect();
+                std::future::ready(Ok::<_, ()>(PageData {
+                    page: page.page,
+                    size: page.size,
+                    total: total as u64,
+                    data,
+                }))
+            };
+            let future = super::fetch_all_pages(QueryPage { page, size: 10 }, max_calls, fetch);
+            let mut future = std::pin::pin!(future);
+            let mut cx = std::task::Context::from_waker(std::task::Waker::noop());
+            match future.as_mut().poll(&mut cx) {
+                std::task::Poll::Ready(result) => result.unwrap(),
+                std::task::Poll::Pending => unreachable!(),
+            }
+        };
+
+        assert_eq!(run(25, 2, 10), Some((10..25).collect()));
+        assert_eq!(run(25, 3, 1), Some((20..25).collect()));
+        assert_eq!(run(20, 2, 1), Some((10..20).collect())); // 正好取完，不需要再请求
+        assert_eq!(run(25, 1, 2), None);
+    }
+
+    #[cfg(feature = "times")]
+    #[test]
+    fn caches_idempotent_results_per_caller() {
+        use candid::Principal;
+
+        use crate::times::{DurationNanos, TimestampNanos};
+
+        let t = TimestampNanos::from;
+        let (alice, bob) = (Principal::from_slice(&[1]), Principal::from_slice(&[2]));
+        let mut cache = IdempotencyCache::<u32> {
+            ttl: DurationNanos::from(10),
+            ..Default::default()
+        };
+        cache.insert_at(alice, "a".into(), 1, t(0));
+        assert_eq!(cache.find_at(&alice, "a", t(9)), Some(&1));
+        assert_eq!(cache.find_at(&bob, "a", t(9)), None); // 其他调用者不能使用相同的键
+        assert_eq!(cache.find_at(&alice, "a", t(10)), None);
+        cache.insert_at(bob, "a".into(), 2, t(10));
+        assert_eq!(cache.results.len(), 1);
+    }
+}
@@ -12,6 +12,12 @@ pub type CanisterInitArg = Vec<u8>;
 /// Canister Wasm 模块的 SHA-256 hash。
 pub type CanisterCodeHash = Vec<u8>;
 
+/// 计算 Wasm 模块或分块的 SHA-256 hash
+pub fn wasm_module_hash(wasm_module: &[u8]) -> CanisterCodeHash {
+    use sha2::Digest;
+    sha2::Sha256::digest(wasm_module).to_vec()
+}
+
 // ========================= 安装代码 =========================
 
 /// 安装罐子代码
@@ -1,7 +1,12 @@
 //! 和 罐子 的 Cycles 相关
 
+use std::collections::BTreeMap;
+
+use candid::CandidType;
+use serde::{Deserialize, Serialize};
+
 use super::types::DepositCyclesArgs;
-use crate::identity::CanisterId;
+use crate::identity::{CallerId, CanisterId};
 
 const WALLET_BALANCE_METHOD: &str = "wallet_balance";
 const WALLET_RECEIVE_METHOD: &str = "wallet_receive";
@@ -53,6 +58,121 @@ where
     candid::Nat::from(accepted)
 }
 
+// ========================= 安全接收 cycles =========================
+
+/*
+
+不会因为接收失败而报错，并按调用者记录转入的 cycles，保持标准的 wallet_receive : () -> (nat) 接口
+被拒绝时不接受 cycles 并返回 0，cycles 会自动退回调用者
+
+#[ic_cdk::update]
+pub fn wallet_receive() -> candid::Nat {
+    let options = WalletReceiveOptions { max_amount: Some(1_000_000_000_000), reject_anonymous: true };
+    candid::Nat::from(with_mut_state(|s| s.depositors.wallet_receive(&options)))
+}
+
+*/
+
+/// 接收 cycles 的选项
+#[derive(CandidType, Serialize, Deserialize, Debug, Clone, Default)]
+pub struct WalletReceiveOptions {
+    /// 单次最多接收的数量，超出的部分会退回调用者
+    pub max_amount: Option<u128>,
+    /// 是否拒绝匿名身份转入
+    pub reject_anonymous: bool,
+}
+
+impl WalletReceiveOptions {
+    fn check(&self, caller: &CallerId, available: u128) -> Result<u128, WalletReceiveError> {
+        if self.reject_anonymous && *caller == CallerId::anonymous() {
+            return Err(WalletReceiveError::AnonymousRejected);
+        }
+        Ok(self
+            .max_amount
+            .map_or(available, |max_amount| available.min(max_amount)))
+    }
+}
+
+/// 接收 cycles 的错误
+#[derive(CandidType, Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub enum WalletReceiveError {
+    /// 拒绝匿名身份
+    AnonymousRejected,
+}
+impl std::fmt::Display for WalletReceiveError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            WalletReceiveError::AnonymousRejected => write!(f, "anonymous caller is rejected"),
+        }
+    }
+}
+impl std::error::Error for WalletReceiveError {}
+
+/// 接受转入的 cycles，不会报错，返回实际接受的数量
+///
+/// 未接受的部分会自动退回调用者
+pub fn try_wallet_receive(options: &WalletReceiveOptions) -> Result<(CallerId, u128), WalletReceiveError> {
+    let caller = crate::identity::caller();
+    let available = ic_cdk::api::msg_cycles_available();
+    let amount = options.check(&caller, available)?;
+    if amount == 0 {
+        return Ok((caller, 0));
+    }
+    Ok((caller, ic_cdk::api::msg_cycles_accept(amount)))
+}
+
+/// 转入者的累计数据
+#[derive(CandidType, Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct CyclesDepositor {
+    /// 累计转入的 cycles
+    pub total: u128,
+    /// 转入次数
+    pub count: u64,
+}
+
+/// 按调用者记录转入的 cycles
+#[derive(CandidType, Serialize, Deserialize, Debug, Clone, Default)]
+pub struct CyclesDepositors {
+    /// 所有转入的 cycles
+    pub total: u128,
+    /// 每个调用者的累计数据
+    pub depositors: BTreeMap<CallerId, CyclesDepositor>,
+}
+
+impl CyclesDepositors {
+    /// 接受转入的 cycles 并记录到调用者名下，返回实际接受的数量
+    ///
+    /// 被拒绝时不接受任何 cycles，打印原因并返回 0
+    pub fn wallet_receive(&mut self, options: &WalletReceiveOptions) -> u128 {
+        match try_wallet_receive(options) {
+            Ok((caller, accepted)) => {
+                self.record(caller, accepted);
+                accepted
+            }
+            Err(err) => {
+                ic_cdk::println!("wallet_receive rejected: {err}");
+                0
+            }
+        }
+    }
+
+    /// 记录一次转入
+    pub fn record(&mut self, caller: CallerId, amount: u128) {
+        if amount == 0 {
+            return;
+        }
+        self.total = self.total.saturating_add(amount);
+        let depositor = self.depositors.entry(caller).or_default();
+        depositor.total = depositor.total.saturating_add(amount);
+        depositor.count += 1;
+    }
+
+    /// 查询某个调用者的累计数据
+    pub fn find(&self, caller: &CallerId) -> Option<&CyclesDepositor> {
+        self.depositors.get(caller)
+    }
+}
+
 // ========================= 充值 cycles =========================
 
 /// 充值余额
@@ -83,3 +203,36 @@ pub async fn call_wallet_receive(
         .await;
     super::fetch_and_wrap_call_result(canister_id, WALLET_RECEIVE_METHOD, call_result)
 }
+
+#[cfg(test)]
+mod tests {
+    use candid::Principal;
+
+    use super::{CyclesDepositor, CyclesDepositors, WalletReceiveError, WalletReceiveOptions};
+
+    #[test]
+    fn caps_amount_rejects_anonymous_and_tracks_depositors() {
+        let options = WalletReceiveOptions {
+            max_amount: Some(100),
+            reject_anonymous: true,
+        };
+        let alice = Principal::from_slice(&[1]);
+        assert_eq!(options.check(&alice, 250), Ok(100));
+        assert_eq!(options.check(&alice, 40), Ok(40));
+        assert_eq!(
+            options.check(&Principal::anonymous(), 40),
+            Err(WalletReceiveError::AnonymousRejected)
+        );
+        assert_eq!(
+            WalletReceiveOptions::default().check(&Principal::anonymous(), 40),
+            Ok(40)
+        );
+
+        let mut depositors = CyclesDepositors::default();
+        depositors.record(alice, 100);
+        depositors.record(alice, 40);
+        depositors.record(alice, 0);
+        assert_eq!(depositors.total, 140);
+        assert_eq!(depositors.find(&alice), Some(&CyclesDepositor { total: 140, count: 2 }));
+    }
+}
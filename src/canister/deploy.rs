@@ -1,6 +1,11 @@
 //! 和 罐子 的 部署 相关
 
-use super::types::CanisterSettings;
+use std::collections::HashSet;
+
+use candid::CandidType;
+use serde::{Deserialize, Serialize};
+
+use super::types::{CanisterInstallMode, CanisterSettings, ChunkHash};
 
 async fn cleanup_failed_deployment(canister_id: crate::identity::CanisterId) -> super::types::CanisterCallResult<()> {
     crate::canister::life::stop_canister(canister_id).await?;
@@ -45,12 +50,113 @@ pub async fn deploy_canister(
     Ok(canister_id)
 }
 
+// ========================= 分块部署 =========================
+
+/// 分块部署的结果
+#[derive(CandidType, Serialize, Deserialize, Debug, Clone)]
+pub struct ChunkedDeployReport {
+    /// 完整 Wasm 的 SHA-256 hash
+    pub wasm_module_hash: super::codes::CanisterCodeHash,
+    /// 按拼接顺序排列的分块 hash
+    pub chunk_hashes: Vec<ChunkHash>,
+    /// 本次上传的分块数量
+    pub uploaded_chunks: u32,
+    /// 已存在而跳过的分块数量
+    pub skipped_chunks: u32,
+    /// 本次上传的字节数
+    pub uploaded_bytes: u64,
+    /// 是否已清空 chunk store
+    pub cleared: bool,
+    /// 清空 chunk store 失败的原因，代码已经安装成功
+    pub clear_error: Option<super::types::CanisterCallError>,
+}
+
+// 按最大分块大小切分，并计算每个分块的 hash
+fn split_chunks(wasm_module: &[u8]) -> Vec<(&[u8], ChunkHash)> {
+    wasm_module
+        .chunks(super::chunks::MAX_CHUNK_SIZE_IN_BYTES)
+        .map(|chunk| {
+            let hash = super::codes::wasm_module_hash(chunk);
+            (chunk, ChunkHash { hash })
+        })
+        .collect()
+}
+
+/// 通过 chunk store 部署大于安装限制的 Wasm
+///
+/// 按 [`super::chunks::MAX_CHUNK_SIZE_IN_BYTES`] 切分，跳过 chunk store 中已有的分块，上传其余分块后安装。
+/// `store_canister` 为 `None` 时使用 `target_canister` 的 chunk store。
+/// `clear_after` 为 true 时安装成功后清空 chunk store，清空失败不影响安装结果。
+pub async fn deploy_chunked_code(
+    target_canister: crate::identity::CanisterId,
+    mode: CanisterInstallMode,
+    store_canister: Option<crate::identity::CanisterId>,
+    wasm_module: &[u8],
+    arg: super::codes::CanisterInitArg,
+    clear_after: bool,
+) -> super::types::CanisterCallResult<ChunkedDeployReport> {
+    let store = store_canister.unwrap_or(target_canister);
+
+    // 1. 切分并跳过已上传的分块
+    let chunks = split_chunks(wasm_module);
+    let mut stored: HashSet<ChunkHash> = super::chunks::stored_chunks(store).await?.into_iter().collect();
+    let mut report = ChunkedDeployReport {
+        wasm_module_hash: super::codes::wasm_module_hash(wasm_module),
+        chunk_hashes: chunks.iter().map(|(_, hash)| hash.clone()).collect(),
+        uploaded_chunks: 0,
+        skipped_chunks: 0,
+        uploaded_bytes: 0,
+        cleared: false,
+        clear_error: None,
+    };
+
+    // 2. 上传缺少的分块
+    for (chunk, hash) in chunks {
+        if stored.contains(&hash) {
+            report.skipped_chunks += 1;
+            continue;
+        }
+        let uploaded = super::chunks::upload_chunk(store, chunk.to_vec()).await?;
+        if uploaded != hash {
+            return Err(super::types::CanisterCallError::new(
+                store,
+                "ic#upload_chunk",
+                "uploaded chunk hash mismatch",
+            ));
+        }
+        stored.insert(hash);
+        report.uploaded_chunks += 1;
+        report.uploaded_bytes += chunk.len() as u64;
+    }
+
+    // 3. 安装代码
+    super::codes::install_chunked_code(
+        target_canister,
+        mode,
+        store_canister,
+        report.chunk_hashes.clone(),
+        report.wasm_module_hash.clone(),
+        arg,
+    )
+    .await?;
+
+    // 4. 清空 chunk store
+    if clear_after {
+        match super::chunks::clear_chunk_store(store).await {
+            Ok(()) => report.cleared = true,
+            Err(err) => report.clear_error = Some(err),
+        }
+    }
+
+    Ok(report)
+}
+
 #[cfg(test)]
 mod tests {
     use candid::Principal;
 
-    use super::attach_cleanup_result;
-    use crate::canister::types::CanisterCallError;
+    use super::{attach_cleanup_result, split_chunks};
+    use crate::canister::{chunks::MAX_CHUNK_SIZE_IN_BYTES, types::CanisterCallError};
 
     fn error(method: &str, message: &str) -> CanisterCallError {
         CanisterCallError::new(Principal::from_slice(&[1]), method, message)
@@ -72,4 +178,17 @@ mod tests {
         assert!(error.message.contains("manually recover canister"));
         assert!(error.message.contains("stop failed"));
     }
+
+    #[test]
+    fn splits_wasm_at_max_chunk_size() {
+        let wasm = vec![7_u8; MAX_CHUNK_SIZE_IN_BYTES * 2 + 1];
+        let chunks = split_chunks(&wasm);
+        assert_eq!(
+            chunks.iter().map(|(chunk, _)| chunk.len()).collect::<Vec<_>>(),
+            vec![MAX_CHUNK_SIZE_IN_BYTES, MAX_CHUNK_SIZE_IN_BYTES, 1]
+        );
+        assert_eq!(chunks[0].1, chunks[1].1);
+        assert_ne!(chunks[1].1, chunks[2].1);
+        assert_eq!(chunks[2].1.hash.len(), 32);
+    }
 }
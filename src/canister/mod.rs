@@ -16,6 +16,9 @@ pub mod chunks;
 /// deploy
 pub mod deploy;
 
+/// 快照
+pub mod snapshot;
+
 /// settings
 pub mod settings;
 
@@ -109,3 +112,30 @@ fn wrap_call_result<E: Display>(
 ) -> types::CanisterCallResult<()> {
     call_result.map_err(|err| crate::canister::types::CanisterCallError::new(canister_id, method, err))
 }
+
+// ========================= 并发调用 =========================
+
+// 同时等待多个调用，按传入顺序返回结果
+#[cfg(feature = "functions")]
+pub(crate) async fn join_all<F: Future>(futures: Vec<F>) -> Vec<F::Output> {
+    let mut futures: Vec<std::pin::Pin<Box<F>>> = futures.into_iter().map(Box::pin).collect();
+    let mut outputs: Vec<Option<F::Output>> = futures.iter().map(|_| None).collect();
+    std::future::poll_fn(|cx| {
+        let mut pending = false;
+        for (future, output) in futures.iter_mut().zip(outputs.iter_mut()) {
+            if output.is_none() {
+                match future.as_mut().poll(cx) {
+                    std::task::Poll::Ready(value) => *output = Some(value),
+                    std::task::Poll::Pending => pending = true,
+                }
+            }
+        }
+        if pending {
+            std::task::Poll::Pending
+        } else {
+            std::task::Poll::Ready(())
+        }
+    })
+    .await;
+    outputs.into_iter().flatten().collect()
+}
@@ -16,3 +16,173 @@ pub async fn update_settings(
         message: err.to_string(),
     })
 }
+
+// ========================= 控制者 =========================
+
+/// 控制者最多数量
+const MAX_CONTROLLERS: usize = 10;
+
+/// 控制者变更
+#[derive(candid::CandidType, serde::Serialize, serde::Deserialize, Debug, Clone, PartialEq, Eq)]
+pub enum ControllerChange {
+    /// 增加控制者
+    Add(Vec<candid::Principal>),
+    /// 移除控制者
+    Remove(Vec<candid::Principal>),
+    /// 替换控制者
+    Replace {
+        /// 被替换的控制者
+        old: candid::Principal,
+        /// 新的控制者
+        new: candid::Principal,
+    },
+}
+
+/// 计算变更后的控制者
+///
+/// 不允许移除最后一个控制者和当前罐子自身，除非 `force` 为 true
+fn compute_controllers(
+    current: &[candid::Principal],
+    change: &ControllerChange,
+    self_canister_id: &candid::Principal,
+    force: bool,
+) -> Result<Vec<candid::Principal>, String> {
+    let mut controllers = current.to_vec();
+    match change {
+        ControllerChange::Add(added) => {
+            for controller in added {
+                if !controllers.contains(controller) {
+                    controllers.push(*controller);
+                }
+            }
+        }
+        ControllerChange::Remove(removed) => controllers.retain(|controller| !removed.contains(controller)),
+        ControllerChange::Replace { old, new } => {
+            if !controllers.contains(old) {
+                return Err(format!("{} is not a controller", old.to_text()));
+            }
+            controllers.retain(|controller| controller != old);
+            if !controllers.contains(new) {
+                controllers.push(*new);
+            }
+        }
+    }
+
+    if MAX_CONTROLLERS < controllers.len() {
+        return Err(format!(
+            "too many controllers: {} > {MAX_CONTROLLERS}",
+            controllers.len()
+        ));
+    }
+    if !force {
+        if controllers.is_empty() {
+            return Err("can not remove the last controller".into());
+        }
+        if current.contains(self_canister_id) && !controllers.contains(self_canister_id) {
+            return Err(format!("can not remove self canister {}", self_canister_id.to_text()));
+        }
+    }
+    Ok(controllers)
+}
+
+/// 变更控制者，返回变更后的控制者
+/// ! Only the controllers of the canister
+/// 先通过 canister_status 读取当前控制者，再通过一次 update_settings 只更新控制者，其他设置保持不变
+pub async fn change_controllers(
+    canister_id: crate::identity::CanisterId,
+    change: ControllerChange,
+    force: bool,
+) -> super::types::CanisterCallResult<Vec<candid::Principal>> {
+    let current = super::status::canister_status(canister_id).await?.settings.controllers;
+    let controllers =
+        compute_controllers(&current, &change, &crate::identity::self_canister_id(), force).map_err(|err| {
+            crate::canister::types::CanisterCallError::new(canister_id, "ic#update_settings#controllers", err)
+        })?;
+    if controllers == current {
+        return Ok(controllers); // 没有变化
+    }
+    update_settings(
+        canister_id,
+        CanisterSettings {
+            controllers: Some(controllers.clone()),
+            ..Default::default()
+        },
+    )
+    .await?;
+    Ok(controllers)
+}
+
+/// 增加控制者
+pub async fn add_controllers(
+    canister_id: crate::identity::CanisterId,
+    controllers: Vec<candid::Principal>,
+) -> super::types::CanisterCallResult<Vec<candid::Principal>> {
+    change_controllers(canister_id, ControllerChange::Add(controllers), false).await
+}
+
+/// 移除控制者
+pub async fn remove_controllers(
+    canister_id: crate::identity::CanisterId,
+    controllers: Vec<candid::Principal>,
+    force: bool,
+) -> super::types::CanisterCallResult<Vec<candid::Principal>> {
+    change_controllers(canister_id, ControllerChange::Remove(controllers), force).await
+}
+
+/// 替换控制者
+pub async fn replace_controller(
+    canister_id: crate::identity::CanisterId,
+    old: candid::Principal,
+    new: candid::Principal,
+    force: bool,
+) -> super::types::CanisterCallResult<Vec<candid::Principal>> {
+    change_controllers(canister_id, ControllerChange::Replace { old, new }, force).await
+}
+
+#[cfg(test)]
+mod tests {
+    use candid::Principal;
+
+    use super::{ControllerChange, compute_controllers};
+
+    #[test]
+    fn refuses_to_remove_last_or_self_unless_forced() {
+        let me = Principal::from_slice(&[1]);
+        let alice = Principal::from_slice(&[2]);
+        let bob = Principal::from_slice(&[3]);
+        let current = vec![me, alice];
+
+        assert_eq!(
+            compute_controllers(&current, &ControllerChange::Add(vec![alice, bob]), &me, false),
+            Ok(vec![me, alice, bob])
+        );
+        assert_eq!(
+            compute_controllers(
+                &current,
+                &ControllerChange::Replace { old: alice, new: bob },
+                &me,
+                false
+            ),
+            Ok(vec![me, bob])
+        );
+        assert!(
+            compute_controllers(
+                &current,
+                &ControllerChange::Replace { old: bob, new: alice },
+                &me,
+                false
+            )
+            .is_err()
+        );
+        assert!(compute_controllers(&current, &ControllerChange::Remove(vec![me]), &me, false).is_err());
+        assert_eq!(
+            compute_controllers(&current, &ControllerChange::Remove(vec![me]), &me, true),
+            Ok(vec![alice])
+        );
+        assert!(compute_controllers(&[alice], &ControllerChange::Remove(vec![alice]), &me, false).is_err());
+        assert_eq!(
+            compute_controllers(&[alice], &ControllerChange::Remove(vec![alice]), &me, true),
+            Ok(vec![])
+        );
+    }
+}
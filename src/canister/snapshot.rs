@@ -0,0 +1,158 @@
+//! 和 罐子 的 快照 相关
+
+use super::types::{
+    DeleteCanisterSnapshotArgs, ListCanisterSnapshotsArgs, LoadCanisterSnapshotArgs, Snapshot, SnapshotId,
+    TakeCanisterSnapshotArgs, UpgradeFlags,
+};
+use crate::identity::CanisterId;
+
+// ========================= 快照 =========================
+
+/// 创建快照，罐子需要处于停止状态
+/// ! Only the controllers of the canister
+/// `replace_snapshot` 用于替换已有的快照，快照数量达到上限时必须指定
+/// <https://docs.internetcomputer.org/references/management-canister/#take_canister_snapshot>
+pub async fn take_canister_snapshot(
+    canister_id: CanisterId,
+    replace_snapshot: Option<SnapshotId>,
+) -> super::types::CanisterCallResult<Snapshot> {
+    ic_cdk_management_canister::take_canister_snapshot(&TakeCanisterSnapshotArgs {
+        canister_id,
+        replace_snapshot,
+        uninstall_code: None,
+        sender_canister_version: None,
+    })
+    .await
+    .map_err(|err| super::types::CanisterCallError::new(canister_id, "ic#take_canister_snapshot", err))
+}
+
+/// 查询所有快照
+/// ! Only the controllers of the canister
+/// <https://docs.internetcomputer.org/references/management-canister/#list_canister_snapshots>
+pub async fn list_canister_snapshots(canister_id: CanisterId) -> super::types::CanisterCallResult<Vec<Snapshot>> {
+    ic_cdk_management_canister::list_canister_snapshots(&ListCanisterSnapshotsArgs { canister_id })
+        .await
+        .map_err(|err| super::types::CanisterCallError::new(canister_id, "ic#list_canister_snapshots", err))
+}
+
+/// 加载快照，罐子需要处于停止状态
+/// ! Only the controllers of the canister
+/// <https://docs.internetcomputer.org/references/management-canister/#load_canister_snapshot>
+pub async fn load_canister_snapshot(
+    canister_id: CanisterId,
+    snapshot_id: SnapshotId,
+) -> super::types::CanisterCallResult<()> {
+    let call_result = ic_cdk_management_canister::load_canister_snapshot(&LoadCanisterSnapshotArgs {
+        canister_id,
+        snapshot_id,
+    })
+    .await;
+    super::wrap_call_result(canister_id, "ic#load_canister_snapshot", call_result)
+}
+
+/// 删除快照
+/// ! Only the controllers of the canister
+/// <https://docs.internetcomputer.org/references/management-canister/#delete_canister_snapshot>
+pub async fn delete_canister_snapshot(
+    canister_id: CanisterId,
+    snapshot_id: SnapshotId,
+) -> super::types::CanisterCallResult<()> {
+    let call_result = ic_cdk_management_canister::delete_canister_snapshot(&DeleteCanisterSnapshotArgs {
+        canister_id,
+        snapshot_id,
+    })
+    .await;
+    super::wrap_call_result(canister_id, "ic#delete_canister_snapshot", call_result)
+}
+
+// ========================= 带快照的升级 =========================
+
+fn attach_rollback_result(
+    mut upgrade_error: super::types::CanisterCallError,
+    snapshot_id: &SnapshotId,
+    rollback_result: super::types::CanisterCallResult<()>,
+) -> super::types::CanisterCallError {
+    upgrade_error.message = match rollback_result {
+        Ok(()) => format!(
+            "{}; the canister was rolled back to the snapshot",
+            upgrade_error.message
+        ),
+        Err(rollback_error) => format!(
+            "{}; automatic rollback failed, manually load snapshot {} of canister {}: {}",
+            upgrade_error.message,
+            hex_snapshot_id(snapshot_id),
+            upgrade_error.canister_id.to_text(),
+            rollback_error
+        ),
+    };
+    upgrade_error
+}
+
+fn hex_snapshot_id(snapshot_id: &SnapshotId) -> String {
+    snapshot_id.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+async fn rollback(canister_id: CanisterId, snapshot_id: SnapshotId) -> super::types::CanisterCallResult<()> {
+    load_canister_snapshot(canister_id, snapshot_id).await?;
+    crate::canister::life::start_canister(canister_id).await
+}
+
+/// 升级前自动创建快照，升级失败时回滚到快照
+///
+/// 罐子会先被停止，升级完成或回滚后重新启动。不能用于升级调用者自身。
+/// 只会替换 `replace_snapshot` 指定的快照，通常是上一次本方法返回的快照；
+/// 不指定时快照数量达到上限会直接返回错误，不会删除已有的快照。返回本次创建的快照。
+pub async fn upgrade_code_with_snapshot(
+    canister_id: CanisterId,
+    wasm_module: super::codes::CanisterCodeWasm,
+    arg: Option<super::codes::CanisterInitArg>,
+    pre_upgrade: Option<UpgradeFlags>,
+    replace_snapshot: Option<SnapshotId>,
+) -> super::types::CanisterCallResult<Snapshot> {
+    // 1. 停止罐子并创建快照
+    crate::canister::life::stop_canister(canister_id).await?;
+    let snapshot = match take_canister_snapshot(canister_id, replace_snapshot).await {
+        Ok(snapshot) => snapshot,
+        Err(err) => {
+            // 没有改动代码，尽量恢复运行
+            let _ = crate::canister::life::start_canister(canister_id).await;
+            return Err(err);
+        }
+    };
+
+    // 2. 升级代码，失败则回滚
+    if let Err(upgrade_error) = super::codes::upgrade_code(canister_id, wasm_module, arg, pre_upgrade).await {
+        let rollback_result = rollback(canister_id, snapshot.id.clone()).await;
+        return Err(attach_rollback_result(upgrade_error, &snapshot.id, rollback_result));
+    }
+
+    // 3. 重新启动
+    crate::canister::life::start_canister(canister_id).await?;
+    Ok(snapshot)
+}
+
+#[cfg(test)]
+mod tests {
+    use candid::Principal;
+
+    use super::attach_rollback_result;
+    use crate::canister::types::CanisterCallError;
+
+    fn error(method: &str, message: &str) -> CanisterCallError {
+        CanisterCallError::new(Principal::from_slice(&[1]), method, message)
+    }
+
+    #[test]
+    fn reports_rollback_outcome() {
+        let rolled_back = attach_rollback_result(error("ic#install_code#upgrade", "trapped"), &vec![0xab], Ok(()));
+        assert!(rolled_back.message.contains("rolled back to the snapshot"));
+
+        let failed = attach_rollback_result(
+            error("ic#install_code#upgrade", "trapped"),
+            &vec![0xab, 0x01],
+            Err(error("ic#load_canister_snapshot", "load failed")),
+        );
+        assert!(failed.message.contains("manually load snapshot ab01"));
+        assert!(failed.message.contains("load failed"));
+    }
+}
@@ -94,9 +94,15 @@ pub struct CanisterMetricsResult {
 
 // ===================== 常用模块 =====================
 
+#[cfg(feature = "times")]
+pub use super::call::IdempotencyCache;
 pub use super::{
+    call::{CallOptions, IdempotencyKey, IdempotentArg},
     chunks::CanisterCodeChunk,
     codes::{CanisterCodeHash, CanisterCodeWasm, CanisterInitArg},
+    cycles::{CyclesDepositor, CyclesDepositors, WalletReceiveError, WalletReceiveOptions},
+    deploy::ChunkedDeployReport,
+    settings::ControllerChange,
 };
 pub use ic_cdk_management_canister::*;
 
@@ -1,6 +1,9 @@
 /// 页面
 pub mod pages;
 
+/// 查询条件
+pub mod query;
+
 /// 结果
 pub mod result;
 
@@ -1,9 +1,32 @@
-use std::{borrow::BorrowMut, cell::RefCell};
+use std::{borrow::BorrowMut, cell::RefCell, collections::HashSet, hash::Hash, thread::LocalKey};
 
 thread_local! {
     static CALL_ONCE: RefCell<bool> = const { RefCell::new(false) };
 }
 
+/// 拦截错误
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CallOnceError {
+    /// 相同的键正在执行中
+    Busy,
+    /// 同时执行的数量已达上限
+    TooManyHolders {
+        /// 最多同时执行的数量
+        max_holders: usize,
+    },
+}
+impl std::fmt::Display for CallOnceError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CallOnceError::Busy => write!(f, "Too many request."),
+            CallOnceError::TooManyHolders { max_holders } => {
+                write!(f, "Too many request. max holders: {max_holders}")
+            }
+        }
+    }
+}
+impl std::error::Error for CallOnceError {}
+
 /// 拦截对象
 #[non_exhaustive]
 pub struct CallOnceGuard;
@@ -17,11 +40,166 @@ impl Drop for CallOnceGuard {
 /// 调用一次
 #[inline]
 pub fn call_once_guard() -> CallOnceGuard {
+    crate::common::trap(try_call_once_guard()) // ! 中止执行
+}
+
+/// 调用一次，已有调用在执行中则返回错误
+#[inline]
+pub fn try_call_once_guard() -> Result<CallOnceGuard, CallOnceError> {
     if CALL_ONCE.with(|o| *o.borrow()) {
-        ic_cdk::trap("Too many request."); // ! 中止执行
+        return Err(CallOnceError::Busy);
     }
 
     CALL_ONCE.with_borrow_mut(|o| *o.borrow_mut() = true);
 
-    CallOnceGuard
+    Ok(CallOnceGuard)
+}
+
+// ================== 按键拦截 ==================
+
+/*
+
+按键拦截需要自行声明存放正在执行的键的位置
+
+thread_local! {
+    static CALLER_LOCKS: RefCell<CallOnceKeys<CallerId>> = RefCell::new(CallOnceKeys::new(Some(100)));
+}
+
+#[ic_cdk::update]
+async fn transfer() {
+    let _guard = call_once_guard_by(&CALLER_LOCKS, ic_canister_kit::identity::caller());
+    // ...
+}
+
+*/
+
+/// 正在执行中的键
+#[derive(Debug, Clone)]
+pub struct CallOnceKeys<K> {
+    /// 正在执行中的键
+    active: HashSet<K>,
+    /// 最多同时执行的数量，没有则不限制
+    max_holders: Option<usize>,
+}
+
+impl<K> Default for CallOnceKeys<K> {
+    fn default() -> Self {
+        Self {
+            active: HashSet::new(),
+            max_holders: None,
+        }
+    }
+}
+
+impl<K: Eq + Hash> CallOnceKeys<K> {
+    /// 构造
+    pub fn new(max_holders: Option<usize>) -> Self {
+        Self {
+            active: HashSet::new(),
+            max_holders,
+        }
+    }
+
+    /// 正在执行中的键
+    pub fn active(&self) -> &HashSet<K> {
+        &self.active
+    }
+
+    /// 最多同时执行的数量
+    pub fn max_holders(&self) -> Option<usize> {
+        self.max_holders
+    }
+
+    /// 修改最多同时执行的数量，不影响已经执行中的键
+    pub fn set_max_holders(&mut self, max_holders: Option<usize>) {
+        self.max_holders = max_holders;
+    }
+
+    /// 某个键是否正在执行
+    pub fn is_active(&self, key: &K) -> bool {
+        self.active.contains(key)
+    }
+
+    fn acquire(&mut self, key: K) -> Result<(), CallOnceError> {
+        if self.active.contains(&key) {
+            return Err(CallOnceError::Busy);
+        }
+        if let Some(max_holders) = self.max_holders
+            && max_holders <= self.active.len()
+        {
+            return Err(CallOnceError::TooManyHolders { max_holders });
+        }
+        self.active.insert(key);
+        Ok(())
+    }
+}
+
+/// 按键拦截对象，离开作用域时释放对应的键
+#[must_use = "dropping the guard immediately releases the key"]
+pub struct KeyedCallOnceGuard<K: Eq + Hash + 'static> {
+    keys: &'static LocalKey<RefCell<CallOnceKeys<K>>>,
+    key: Option<K>,
+}
+
+impl<K: Eq + Hash + 'static> KeyedCallOnceGuard<K> {
+    /// 被拦截的键
+    pub fn key(&self) -> Option<&K> {
+        self.key.as_ref()
+    }
+}
+
+impl<K: Eq + Hash + 'static> Drop for KeyedCallOnceGuard<K> {
+    fn drop(&mut self) {
+        if let Some(key) = self.key.take() {
+            self.keys.with_borrow_mut(|keys| keys.active.remove(&key));
+        }
+    }
+}
+
+/// 按键调用一次
+/// ! 可能中止程序
+#[inline]
+pub fn call_once_guard_by<K: Eq + Hash + Clone + 'static>(
+    keys: &'static LocalKey<RefCell<CallOnceKeys<K>>>,
+    key: K,
+) -> KeyedCallOnceGuard<K> {
+    crate::common::trap(try_call_once_guard_by(keys, key)) // ! 中止执行
+}
+
+/// 按键调用一次，相同的键在执行中或同时执行数量已达上限则返回错误
+#[inline]
+pub fn try_call_once_guard_by<K: Eq + Hash + Clone + 'static>(
+    keys: &'static LocalKey<RefCell<CallOnceKeys<K>>>,
+    key: K,
+) -> Result<KeyedCallOnceGuard<K>, CallOnceError> {
+    keys.with_borrow_mut(|k| k.acquire(key.clone()))?;
+    Ok(KeyedCallOnceGuard { keys, key: Some(key) })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+
+    use super::*;
+
+    thread_local! {
+        static KEYS: RefCell<CallOnceKeys<u8>> = RefCell::new(CallOnceKeys::new(Some(2)));
+    }
+
+    #[test]
+    fn keyed_guard_blocks_only_the_same_key_and_respects_max_holders() {
+        let first = try_call_once_guard_by(&KEYS, 1).unwrap();
+        assert_eq!(try_call_once_guard_by(&KEYS, 1).err(), Some(CallOnceError::Busy));
+
+        let second = try_call_once_guard_by(&KEYS, 2).unwrap();
+        assert_eq!(
+            try_call_once_guard_by(&KEYS, 3).err(),
+            Some(CallOnceError::TooManyHolders { max_holders: 2 })
+        );
+
+        drop(first);
+        assert!(KEYS.with_borrow(|keys| !keys.is_active(&1) && keys.is_active(&2)));
+        assert!(try_call_once_guard_by(&KEYS, 3).is_ok());
+        drop(second);
+    }
 }
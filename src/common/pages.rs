@@ -28,6 +28,29 @@ pub enum QueryPageError {
         #[serde(alias = "max")]
         max_page_size: u32,
     }, // size can not be 0 and has max value
+
+    /// 错误的游标
+    WrongCursor,
+
+    /// 不支持的查询字段
+    UnknownField {
+        /// 字段名称
+        field: String,
+    },
+
+    /// 字段值的类型与查询条件不一致
+    TypeMismatch {
+        /// 字段名称
+        field: String,
+    },
+
+    /// 查询条件太多
+    TooManyConditions {
+        /// 条件数量
+        size: u32,
+        /// 最大条件数量
+        max_conditions: u32,
+    },
 }
 impl std::fmt::Display for QueryPageError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
@@ -40,6 +63,12 @@ impl std::fmt::Display for QueryPageError {
                     write!(f, "max_page_size({max_page_size}) < size({size})")
                 }
             }
+            QueryPageError::WrongCursor => write!(f, "invalid cursor"),
+            QueryPageError::UnknownField { field } => write!(f, "unknown query field: {field}"),
+            QueryPageError::TypeMismatch { field } => write!(f, "query field type mismatch: {field}"),
+            QueryPageError::TooManyConditions { size, max_conditions } => {
+                write!(f, "max_conditions({max_conditions}) < conditions({size})")
+            }
         }
     }
 }
@@ -225,12 +254,168 @@ impl QueryPage {
     }
 }
 
+// ============= 游标分页查询 =============
+
+/// 分页游标，由上一页结果返回，客户端不应解析其内容
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, CandidType)]
+pub struct PageCursor(Vec<u8>);
+
+impl PageCursor {
+    fn encode<K: CandidType>(key: &K) -> Result<Self, QueryPageError> {
+        candid::encode_one(key)
+            .map(Self)
+            .map_err(|_| QueryPageError::WrongCursor)
+    }
+
+    fn decode<K: CandidType + for<'de> Deserialize<'de>>(&self) -> Result<K, QueryPageError> {
+        candid::decode_one(&self.0).map_err(|_| QueryPageError::WrongCursor)
+    }
+}
+
+/// 游标翻页方向
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, CandidType)]
+pub enum CursorDirection {
+    /// 正序，从小到大
+    #[default]
+    Forward,
+    /// 倒序，从大到小
+    Backward,
+}
+
+/// 游标分页对象
+///
+/// 以上一页最后一条数据的键作为起点，数据在请求之间增删也不会重复或遗漏
+#[derive(Debug, Clone, Serialize, Deserialize, CandidType)]
+pub struct CursorPage {
+    /// 上一页返回的游标，第一页为空
+    pub cursor: Option<PageCursor>,
+    /// 翻页方向
+    #[serde(default)]
+    pub direction: CursorDirection,
+    /// 每页大小
+    pub limit: u32,
+}
+
+/// 游标分页查询结果
+#[derive(Debug, Clone, Serialize, Deserialize, CandidType)]
+pub struct CursorPageData<T> {
+    /// 查到的分页数据
+    pub data: Vec<T>,
+    /// 下一页的游标，没有更多数据则为空
+    pub next_cursor: Option<PageCursor>,
+}
+
+impl CursorPage {
+    /// 检查分页选项是否有效
+    #[inline]
+    pub fn check(&self, max_page_size: u32) -> Result<(), QueryPageError> {
+        if self.limit == 0 || max_page_size < self.limit {
+            return Err(QueryPageError::WrongSize {
+                size: self.limit,
+                max_page_size,
+            });
+        }
+        Ok(())
+    }
+
+    /// 解析游标中的键
+    pub fn decode_cursor<K: CandidType + for<'de> Deserialize<'de>>(&self) -> Result<Option<K>, QueryPageError> {
+        self.cursor.as_ref().map(PageCursor::decode).transpose()
+    }
+
+    /// 从已经定位到起点的数据中取出一页
+    pub fn from_iter<T, K: CandidType>(
+        &self,
+        iter: impl Iterator<Item = T>,
+        key: impl Fn(&T) -> K,
+    ) -> Result<CursorPageData<T>, QueryPageError> {
+        let mut iter = iter.peekable();
+        let data: Vec<T> = iter.by_ref().take(self.limit as usize).collect();
+        let next_cursor = match (iter.peek(), data.last()) {
+            (Some(_), Some(last)) => Some(PageCursor::encode(&key(last))?),
+            _ => None,
+        };
+        Ok(CursorPageData { data, next_cursor })
+    }
+
+    /// 对按键升序排列的数据进行游标分页查询
+    #[inline]
+    pub fn query_by_list<'a, T, K>(
+        &self,
+        list: &'a [T],
+        max_page_size: u32,
+        key: impl Fn(&T) -> K,
+    ) -> Result<CursorPageData<&'a T>, QueryPageError>
+    where
+        K: Ord + CandidType + for<'de> Deserialize<'de>,
+    {
+        self.query_by_list_and_filter(list, max_page_size, key, |_| true)
+    }
+
+    /// 对按键升序排列的数据进行游标过滤分页查询
+    pub fn query_by_list_and_filter<'a, T, K, F>(
+        &self,
+        list: &'a [T],
+        max_page_size: u32,
+        key: impl Fn(&T) -> K,
+        filter: F, // 过滤条件
+    ) -> Result<CursorPageData<&'a T>, QueryPageError>
+    where
+        K: Ord + CandidType + for<'de> Deserialize<'de>,
+        F: Fn(&T) -> bool,
+    {
+        self.check(max_page_size)?;
+        let cursor: Option<K> = self.decode_cursor()?;
+        match self.direction {
+            CursorDirection::Forward => {
+                let start = cursor.map_or(0, |cursor| list.partition_point(|item| key(item) <= cursor));
+                self.from_iter(list[start..].iter().filter(|item| filter(item)), |item| key(item))
+            }
+            CursorDirection::Backward => {
+                let end = cursor.map_or(list.len(), |cursor| list.partition_point(|item| key(item) < cursor));
+                self.from_iter(list[..end].iter().rev().filter(|item| filter(item)), |item| key(item))
+            }
+        }
+    }
+
+    /// 对稳定内存中的 BTreeMap 进行游标分页查询
+    #[cfg(feature = "stable")]
+    pub fn query_by_stable_map<K, V, M>(
+        &self,
+        map: &ic_stable_structures::BTreeMap<K, V, M>,
+        max_page_size: u32,
+    ) -> Result<CursorPageData<(K, V)>, QueryPageError>
+    where
+        K: ic_stable_structures::Storable + Ord + Clone + CandidType + for<'de> Deserialize<'de>,
+        V: ic_stable_structures::Storable,
+        M: ic_stable_structures::Memory,
+    {
+        use std::ops::Bound;
+
+        self.check(max_page_size)?;
+        let cursor: Option<K> = self.decode_cursor()?;
+        let key = |(key, _): &(K, V)| key.clone();
+        match self.direction {
+            CursorDirection::Forward => {
+                let start = cursor.map_or(Bound::Unbounded, Bound::Excluded);
+                let iter = map.range((start, Bound::Unbounded)).map(|entry| entry.into_pair());
+                self.from_iter(iter, key)
+            }
+            CursorDirection::Backward => {
+                let end = cursor.map_or(Bound::Unbounded, Bound::Excluded);
+                let iter = map.range((Bound::Unbounded, end)).rev().map(|entry| entry.into_pair());
+                self.from_iter(iter, key)
+            }
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use ciborium::value::Value;
     use serde::Serialize;
 
-    use super::{PageData, QueryPage, QueryPageError};
+    use super::{CursorDirection, CursorPage, PageData, QueryPage, QueryPageError};
 
     #[test]
     fn rejects_zero_page_and_invalid_size() {
@@ -282,6 +467,80 @@ mod tests {
         assert_eq!(calls.get(), data.len());
     }
 
+    #[test]
+    fn cursor_pages_do_not_repeat_or_skip_when_list_changes() {
+        let mut data: Vec<u64> = (1..=5).collect();
+        let mut page = CursorPage {
+            cursor: None,
+            direction: CursorDirection::Forward,
+            limit: 2,
+        };
+        let first = page.query_by_list(&data, 10, |value| *value).unwrap();
+        assert_eq!(first.data, vec![&1, &2]);
+        page.cursor = first.next_cursor;
+
+        // 请求之间删除了已读的数据并追加了新数据
+        data.remove(0);
+        data.push(6);
+        let second = page.query_by_list(&data, 10, |value| *value).unwrap();
+        assert_eq!(second.data, vec![&3, &4]);
+        page.cursor = second.next_cursor;
+        let third = page
+            .query_by_list_and_filter(&data, 10, |value| *value, |value| value % 2 == 0)
+            .unwrap();
+        assert_eq!(third.data, vec![&6]);
+        assert!(third.next_cursor.is_none());
+
+        let backward = CursorPage {
+            cursor: None,
+            direction: CursorDirection::Backward,
+            limit: 4,
+        };
+        let last = backward.query_by_list(&data, 10, |value| *value).unwrap();
+        assert_eq!(last.data, vec![&6, &5, &4, &3]);
+        let backward = CursorPage {
+            cursor: last.next_cursor,
+            ..backward
+        };
+        assert_eq!(
+            backward.query_by_list(&data, 10, |value| *value).unwrap().data,
+            vec![&2]
+        );
+
+        assert!(matches!(
+            CursorPage {
+                limit: 0,
+                ..page.clone()
+            }
+            .query_by_list(&data, 10, |value| *value),
+            Err(QueryPageError::WrongSize { .. })
+        ));
+        assert!(matches!(
+            page.query_by_list(&data, 10, |value| value.to_string()),
+            Err(QueryPageError::WrongCursor)
+        ));
+    }
+
+    #[cfg(feature = "stable")]
+    #[test]
+    fn cursor_pages_over_stable_btree_map() {
+        let mut map = ic_stable_structures::BTreeMap::new(ic_stable_structures::DefaultMemoryImpl::default());
+        for key in 0..5_u64 {
+            map.insert(key, key * 10);
+        }
+        let mut page = CursorPage {
+            cursor: None,
+            direction: CursorDirection::Backward,
+            limit: 3,
+        };
+        let first = page.query_by_stable_map(&map, 10).unwrap();
+        assert_eq!(first.data, vec![(4, 40), (3, 30), (2, 20)]);
+        page.cursor = first.next_cursor;
+        let second = page.query_by_stable_map(&map, 10).unwrap();
+        assert_eq!(second.data, vec![(1, 10), (0, 0)]);
+        assert!(second.next_cursor.is_none());
+    }
+
     #[test]
     fn preserves_page_total_and_deserializes_legacy_error_name() {
         #[derive(Serialize)]
@@ -0,0 +1,399 @@
+use std::cmp::Ordering;
+
+use candid::{CandidType, Principal};
+use serde::{Deserialize, Serialize};
+
+use super::pages::{PageData, QueryPage, QueryPageError};
+
+/*
+
+客户端通过 Query 指定过滤条件、排序和分页，罐子为自己的类型实现 Queryable 即可
+
+impl Queryable for Order {
+    const QUERY_FIELDS: &'static [&'static str] = &["id", "owner", "amount", "memo"];
+
+    fn query_field(&self, field: &str) -> Option<QueryValue> {
+        Some(match field {
+            "id" => self.id.into(),
+            "owner" => self.owner.into(),
+            "amount" => self.amount.into(),
+            "memo" => self.memo.clone().into(),
+            _ => return None,
+        })
+    }
+}
+
+#[ic_cdk::query]
+fn query_orders(query: Query) -> Result<PageData<Order>, QueryPageError> {
+    with_state(|s| {
+        let page = query.query_by_list(&s.orders, 100, 10)?;
+        Ok(query.page.from_data(page.total, page.data.into_iter().cloned().collect()))
+    })
+}
+
+*/
+
+/// 查询使用的值，只有同一类型的值可以比较
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, CandidType)]
+pub enum QueryValue {
+    /// 布尔
+    Bool(bool),
+    /// 无符号整数
+    Nat(u128),
+    /// 有符号整数
+    Int(i128),
+    /// 文本
+    Text(String),
+    /// 身份
+    Principal(Principal),
+}
+
+impl QueryValue {
+    /// 比较同一类型的值，类型不同则返回 None
+    pub fn compare(&self, other: &Self) -> Option<Ordering> {
+        match (self, other) {
+            (Self::Bool(a), Self::Bool(b)) => Some(a.cmp(b)),
+            (Self::Nat(a), Self::Nat(b)) => Some(a.cmp(b)),
+            (Self::Int(a), Self::Int(b)) => Some(a.cmp(b)),
+            (Self::Text(a), Self::Text(b)) => Some(a.cmp(b)),
+            (Self::Principal(a), Self::Principal(b)) => Some(a.cmp(b)),
+            _ => None,
+        }
+    }
+}
+
+impl From<bool> for QueryValue {
+    fn from(value: bool) -> Self {
+        Self::Bool(value)
+    }
+}
+impl From<u64> for QueryValue {
+    fn from(value: u64) -> Self {
+        Self::Nat(value.into())
+    }
+}
+impl From<u128> for QueryValue {
+    fn from(value: u128) -> Self {
+        Self::Nat(value)
+    }
+}
+impl From<i64> for QueryValue {
+    fn from(value: i64) -> Self {
+        Self::Int(value.into())
+    }
+}
+impl From<i128> for QueryValue {
+    fn from(value: i128) -> Self {
+        Self::Int(value)
+    }
+}
+impl From<String> for QueryValue {
+    fn from(value: String) -> Self {
+        Self::Text(value)
+    }
+}
+impl From<&str> for QueryValue {
+    fn from(value: &str) -> Self {
+        Self::Text(value.to_string())
+    }
+}
+impl From<Principal> for QueryValue {
+    fn from(value: Principal) -> Self {
+        Self::Principal(value)
+    }
+}
+
+/// 过滤方式
+#[derive(Debug, Clone, Serialize, Deserialize, CandidType)]
+pub enum QueryFilterOp {
+    /// 等于
+    Eq(QueryValue),
+    /// 范围，依次为包含下界和包含上界
+    Range(Option<QueryValue>, Option<QueryValue>),
+    /// 属于其中之一
+    In(Vec<QueryValue>),
+    /// 文本包含
+    Contains(String),
+}
+
+/// 字段过滤条件
+#[derive(Debug, Clone, Serialize, Deserialize, CandidType)]
+pub struct QueryFilter {
+    /// 字段名称
+    pub field: String,
+    /// 过滤方式
+    pub op: QueryFilterOp,
+}
+
+impl QueryFilter {
+    fn test(&self, value: Option<&QueryValue>) -> Result<bool, QueryPageError> {
+        let Some(value) = value else {
+            return Ok(false);
+        };
+        let compare = |expected: &QueryValue| {
+            value.compare(expected).ok_or_else(|| QueryPageError::TypeMismatch {
+                field: self.field.clone(),
+            })
+        };
+        Ok(match &self.op {
+            QueryFilterOp::Eq(expected) => compare(expected)?.is_eq(),
+            QueryFilterOp::Range(min, max) => {
+                let min = min.as_ref().map(compare).transpose()?;
+                let max = max.as_ref().map(compare).transpose()?;
+                min.is_none_or(Ordering::is_ge) && max.is_none_or(Ordering::is_le)
+            }
+            QueryFilterOp::In(values) => {
+                let mut found = false;
+                for expected in values {
+                    found |= compare(expected)?.is_eq();
+                }
+                found
+            }
+            QueryFilterOp::Contains(text) => match value {
+                QueryValue::Text(value) => value.contains(text),
+                _ => {
+                    return Err(QueryPageError::TypeMismatch {
+                        field: self.field.clone(),
+                    });
+                }
+            },
+        })
+    }
+
+    // 条件数量，In 按值的个数计算
+    fn conditions(&self) -> usize {
+        match &self.op {
+            QueryFilterOp::In(values) => values.len().max(1),
+            _ => 1,
+        }
+    }
+}
+
+/// 排序方向
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, CandidType)]
+pub enum SortOrder {
+    /// 升序
+    #[default]
+    Asc,
+    /// 降序
+    Desc,
+}
+
+/// 排序键
+#[derive(Debug, Clone, Serialize, Deserialize, CandidType)]
+pub struct QuerySort {
+    /// 字段名称
+    pub field: String,
+    /// 排序方向
+    #[serde(default)]
+    pub order: SortOrder,
+}
+
+/// 查询条件
+#[derive(Debug, Clone, Serialize, Deserialize, CandidType)]
+pub struct Query {
+    /// 过滤条件，需要全部满足
+    #[serde(default)]
+    pub filters: Vec<QueryFilter>,
+    /// 排序键，依次比较，都相等则保持原有顺序
+    #[serde(default)]
+    pub sort: Vec<QuerySort>,
+    /// 分页
+    pub page: QueryPage,
+}
+
+/// 可以按字段查询的数据
+pub trait Queryable {
+    /// 支持查询的字段
+    const QUERY_FIELDS: &'static [&'static str];
+
+    /// 读取字段值，字段不存在则返回 None
+    fn query_field(&self, field: &str) -> Option<QueryValue>;
+}
+
+impl Query {
+    /// 检查查询条件是否有效
+    pub fn check<T: Queryable>(&self, max_page_size: u32, max_conditions: u32) -> Result<(), QueryPageError> {
+        self.page.check(max_page_size)?;
+
+        let size = self.filters.iter().map(QueryFilter::conditions).sum::<usize>() + self.sort.len();
+        let size = u32::try_from(size).unwrap_or(u32::MAX);
+        if max_conditions < size {
+            return Err(QueryPageError::TooManyConditions { size, max_conditions });
+        }
+
+        let fields = self.filters.iter().map(|filter| &filter.field);
+        for field in fields.chain(self.sort.iter().map(|sort| &sort.field)) {
+            if !T::QUERY_FIELDS.contains(&field.as_str()) {
+                return Err(QueryPageError::UnknownField { field: field.clone() });
+            }
+        }
+        Ok(())
+    }
+
+    /// 是否满足过滤条件，字段值与条件类型不一致则返回错误
+    pub fn test<T: Queryable>(&self, item: &T) -> Result<bool, QueryPageError> {
+        for filter in &self.filters {
+            if !filter.test(item.query_field(&filter.field).as_ref())? {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    /// 按排序键比较，缺少字段的排在前面，字段值类型不一致则返回错误
+    pub fn compare<T: Queryable>(&self, a: &T, b: &T) -> Result<Ordering, QueryPageError> {
+        for sort in &self.sort {
+            let ordering = match (a.query_field(&sort.field), b.query_field(&sort.field)) {
+                (Some(a), Some(b)) => a.compare(&b).ok_or_else(|| QueryPageError::TypeMismatch {
+                    field: sort.field.clone(),
+                })?,
+                (a, b) => a.is_some().cmp(&b.is_some()),
+            };
+            let ordering = match sort.order {
+                SortOrder::Asc => ordering,
+                SortOrder::Desc => ordering.reverse(),
+            };
+            if ordering != Ordering::Equal {
+                return Ok(ordering);
+            }
+        }
+        Ok(Ordering::Equal)
+    }
+
+    /// 对所有数据进行过滤、排序和分页查询
+    pub fn query_by_list<'a, T: Queryable>(
+        &self,
+        list: &'a [T],
+        max_page_size: u32,
+        max_conditions: u32,
+    ) -> Result<PageData<&'a T>, QueryPageError> {
+        self.check::<T>(max_page_size, max_conditions)?;
+
+        let mut filtered = Vec::new();
+        for item in list {
+            if self.test(item)? {
+                filtered.push(item);
+            }
+        }
+        let mut list = filtered;
+        if !self.sort.is_empty() {
+            let mut error = None;
+            list.sort_by(|a, b| {
+                self.compare(*a, *b).unwrap_or_else(|err| {
+                    error.get_or_insert(err);
+                    Ordering::Equal
+                })
+            });
+            if let Some(error) = error {
+                return Err(error);
+            }
+        }
+
+        let page = self.page.query_by_list(&list, max_page_size)?;
+        Ok(self
+            .page
+            .from_data(page.total, page.data.into_iter().copied().collect()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use candid::Principal;
+
+    use super::{Query, QueryFilter, QueryFilterOp, QuerySort, QueryValue, Queryable, SortOrder};
+    use crate::common::pages::{QueryPage, QueryPageError};
+
+    struct Order {
+        id: u64,
+        owner: Principal,
+        amount: i64,
+        memo: String,
+    }
+
+    impl Queryable for Order {
+        const QUERY_FIELDS: &'static [&'static str] = &["id", "owner", "amount", "memo"];
+
+        fn query_field(&self, field: &str) -> Option<QueryValue> {
+            Some(match field {
+                "id" => self.id.into(),
+                "owner" => self.owner.into(),
+                "amount" => self.amount.into(),
+                "memo" => self.memo.as_str().into(),
+                _ => return None,
+            })
+        }
+    }
+
+    #[test]
+    fn filters_sorts_and_validates_queries() {
+        let alice = Principal::from_slice(&[1]);
+        let orders: Vec<Order> = (0..6)
+            .map(|id| Order {
+                id,
+                owner: if id % 2 == 0 { alice } else { Principal::anonymous() },
+                amount: [30, 10, 20, 10, 50, 40][id as usize],
+                memo: format!("order-{id}"),
+            })
+            .collect();
+
+        let mut query = Query {
+            filters: vec![
+                QueryFilter {
+                    field: "amount".to_string(),
+                    op: QueryFilterOp::Range(Some(10_i64.into()), Some(40_i64.into())),
+                },
+                QueryFilter {
+                    field: "memo".to_string(),
+                    op: QueryFilterOp::Contains("order".to_string()),
+                },
+            ],
+            sort: vec![
+                QuerySort {
+                    field: "amount".to_string(),
+                    order: SortOrder::Desc,
+                },
+                QuerySort {
+                    field: "id".to_string(),
+                    order: SortOrder::Asc,
+                },
+            ],
+            page: QueryPage { page: 1, size: 3 },
+        };
+        let page = query.query_by_list(&orders, 10, 10).unwrap();
+        assert_eq!(page.total, 5);
+        assert_eq!(
+            page.data.iter().map(|order| order.id).collect::<Vec<_>>(),
+            vec![5, 0, 2]
+        );
+
+        query.filters.push(QueryFilter {
+            field: "owner".to_string(),
+            op: QueryFilterOp::In(vec![alice.into()]),
+        });
+        let page = query.query_by_list(&orders, 10, 10).unwrap();
+        assert_eq!(page.data.iter().map(|order| order.id).collect::<Vec<_>>(), vec![0, 2]);
+
+        // Nat 与 Int 不能比较
+        query.filters[0].op = QueryFilterOp::Range(Some(10_u64.into()), None);
+        assert!(matches!(
+            query.query_by_list(&orders, 10, 10),
+            Err(QueryPageError::TypeMismatch { field }) if field == "amount"
+        ));
+        query.filters[0].op = QueryFilterOp::Range(Some(10_i64.into()), Some(40_i64.into()));
+
+        assert!(matches!(
+            query.query_by_list(&orders, 10, 4),
+            Err(QueryPageError::TooManyConditions { size: 5, .. })
+        ));
+        query.sort[0].field = "unknown".to_string();
+        assert!(matches!(
+            query.query_by_list(&orders, 10, 10),
+            Err(QueryPageError::UnknownField { field }) if field == "unknown"
+        ));
+        assert!(matches!(
+            query.query_by_list(&orders, 2, 10),
+            Err(QueryPageError::WrongSize { .. })
+        ));
+    }
+}
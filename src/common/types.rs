@@ -1,3 +1,5 @@
-pub use super::pages::{PageData, QueryPage, QueryPageError};
+pub use super::pages::{CursorDirection, CursorPage, CursorPageData, PageCursor, PageData, QueryPage, QueryPageError};
+
+pub use super::query::{Query, QueryFilter, QueryFilterOp, QuerySort, QueryValue, Queryable, SortOrder};
 
 pub use super::result::MotokoResult;
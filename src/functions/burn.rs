@@ -0,0 +1,236 @@
+//! cycles 消耗趋势和剩余可用时间
+
+/*
+
+定期采样自身的余额和累计消耗，查询接口返回消耗速度和预计剩余天数
+
+fn start_jobs() {
+    ic_canister_kit::functions::schedule::schedule_start(&Some(HOUR.into()), || async {
+        // 罐子需要是自己的控制者才能查询 canister_metrics
+        if let Ok((balance, consumed)) = ic_canister_kit::functions::burn::sample_self_cycles().await {
+            with_mut_state(|s| s.burn.burn_sample(balance, consumed));
+        }
+    });
+}
+
+#[ic_cdk::query]
+fn cycles_burn_report() -> Option<CyclesBurnReport> {
+    with_state(|s| s.burn.burn_report())
+}
+
+*/
+
+use crate::canister::types::{CanisterCallResult, CyclesConsumed};
+
+/// 按用途划分的累计消耗
+pub type CyclesConsumedByCategory = Vec<(String, u128)>;
+
+/// 将 canister_metrics 的累计消耗转换为按用途划分的列表
+pub fn cycles_consumed_by_category(consumed: &CyclesConsumed) -> CyclesConsumedByCategory {
+    let value = |nat: &candid::Nat| u128::try_from(nat.0.clone()).unwrap_or(u128::MAX);
+    vec![
+        ("memory".to_string(), value(&consumed.memory)),
+        ("compute_allocation".to_string(), value(&consumed.compute_allocation)),
+        ("ingress_induction".to_string(), value(&consumed.ingress_induction)),
+        ("instructions".to_string(), value(&consumed.instructions)),
+        (
+            "request_and_response_transmission".to_string(),
+            value(&consumed.request_and_response_transmission),
+        ),
+        ("uninstall".to_string(), value(&consumed.uninstall)),
+        ("canister_creation".to_string(), value(&consumed.canister_creation)),
+        ("http_outcalls".to_string(), value(&consumed.http_outcalls)),
+        ("burned_cycles".to_string(), value(&consumed.burned_cycles)),
+    ]
+}
+
+/// 查询自身的余额和按用途划分的累计消耗
+pub async fn sample_self_cycles() -> CanisterCallResult<(u128, CyclesConsumedByCategory)> {
+    let canister_id = crate::identity::self_canister_id();
+    let metrics = crate::canister::status::canister_metrics(canister_id).await?;
+    Ok((
+        crate::canister::self_canister_cycles(),
+        cycles_consumed_by_category(&metrics.cycles_consumed),
+    ))
+}
+
+// ================== 功能 ==================
+
+/// cycles 消耗统计
+pub trait CyclesBurnTrackable<Sample, Report> {
+    // 查询
+
+    /// 查询所有采样 正序
+    fn burn_find_all(&self) -> Vec<&Sample>;
+    /// 根据最早和最新的采样计算消耗速度，采样不足时返回 None
+    fn burn_report(&self) -> Option<Report>;
+
+    // 修改
+
+    /// 添加一次采样
+    fn burn_sample(&mut self, balance: u128, consumed: CyclesConsumedByCategory);
+}
+
+// ================== 简单实现 ==================
+
+/// cycles 消耗统计简单实现
+pub mod basic {
+    use std::collections::VecDeque;
+
+    use candid::CandidType;
+    use serde::{Deserialize, Serialize};
+
+    use super::CyclesConsumedByCategory;
+    use crate::{functions::types::CyclesBurnTrackable, types::TimestampNanos};
+
+    const NANOS_PER_DAY: u128 = 24 * 60 * 60 * 1_000_000_000;
+
+    /// 一次采样
+    #[derive(CandidType, Serialize, Deserialize, Debug, Clone)]
+    pub struct CyclesSample {
+        /// 采样时间
+        pub time: TimestampNanos,
+        /// 余额
+        pub balance: u128,
+        /// 按用途划分的累计消耗
+        pub consumed: CyclesConsumedByCategory,
+    }
+
+    /// 消耗报告
+    #[derive(CandidType, Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+    pub struct CyclesBurnReport {
+        /// 最早采样时间
+        pub from: TimestampNanos,
+        /// 最新采样时间
+        pub to: TimestampNanos,
+        /// 最新余额
+        pub balance: u128,
+        /// 每天总消耗
+        pub burn_per_day: u128,
+        /// 按用途划分的每天消耗
+        pub burn_per_day_by_category: Vec<(String, u128)>,
+        /// 按当前速度预计还能运行的天数，没有消耗则为 None
+        pub runway_days: Option<u64>,
+    }
+
+    /// 持久化的采样记录，超出容量时丢弃最早的采样
+    #[derive(CandidType, Serialize, Deserialize, Debug, Clone)]
+    pub struct CyclesBurn {
+        /// 最多保留的采样数量
+        pub capacity: u32,
+        /// 采样记录 正序
+        pub samples: VecDeque<CyclesSample>,
+    }
+
+    impl Default for CyclesBurn {
+        fn default() -> Self {
+            Self {
+                capacity: 24 * 7, // 每小时采样可以保留一周
+                samples: VecDeque::new(),
+            }
+        }
+    }
+
+    impl CyclesBurn {
+        fn sample_at(&mut self, balance: u128, consumed: CyclesConsumedByCategory, now: TimestampNanos) {
+            if self.capacity == 0 {
+                return;
+            }
+            while self.capacity as usize <= self.samples.len() {
+                self.samples.pop_front();
+            }
+            self.samples.push_back(CyclesSample {
+                time: now,
+                balance,
+                consumed,
+            });
+        }
+    }
+
+    impl CyclesBurnTrackable<CyclesSample, CyclesBurnReport> for CyclesBurn {
+        // 查询
+        fn burn_find_all(&self) -> Vec<&CyclesSample> {
+            self.samples.iter().collect()
+        }
+
+        fn burn_report(&self) -> Option<CyclesBurnReport> {
+            let (first, last) = (self.samples.front()?, self.samples.back()?);
+            let elapsed = u128::try_from(last.time.into_inner() - first.time.into_inner()).ok()?;
+            if elapsed == 0 {
+                return None;
+            }
+
+            // 累计消耗是单调递增的计数器，用差值计算速度，不受充值影响
+            let per_day = |delta: u128| delta.saturating_mul(NANOS_PER_DAY) / elapsed;
+            let burn_per_day_by_category: Vec<(String, u128)> = last
+                .consumed
+                .iter()
+                .map(|(category, value)| {
+                    let before = first
+                        .consumed
+                        .iter()
+                        .find(|(c, _)| c == category)
+                        .map_or(0, |(_, value)| *value);
+                    (category.clone(), per_day(value.saturating_sub(before)))
+                })
+                .collect();
+            let burn_per_day = burn_per_day_by_category
+                .iter()
+                .fold(0_u128, |total, (_, value)| total.saturating_add(*value));
+
+            Some(CyclesBurnReport {
+                from: first.time,
+                to: last.time,
+                balance: last.balance,
+                burn_per_day,
+                burn_per_day_by_category,
+                runway_days: (burn_per_day != 0)
+                    .then(|| u64::try_from(last.balance / burn_per_day).unwrap_or(u64::MAX)),
+            })
+        }
+
+        // 修改
+        fn burn_sample(&mut self, balance: u128, consumed: CyclesConsumedByCategory) {
+            self.sample_at(balance, consumed, crate::times::now());
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::{CyclesBurn, NANOS_PER_DAY};
+        use crate::{functions::types::CyclesBurnTrackable, types::TimestampNanos};
+
+        fn consumed(memory: u128, instructions: u128) -> Vec<(String, u128)> {
+            vec![
+                ("memory".to_string(), memory),
+                ("instructions".to_string(), instructions),
+            ]
+        }
+
+        #[test]
+        fn computes_burn_rate_and_runway_from_bounded_samples() {
+            let mut burn = CyclesBurn {
+                capacity: 2,
+                ..Default::default()
+            };
+            burn.sample_at(0, consumed(0, 0), TimestampNanos::from(0));
+            assert!(burn.burn_report().is_none());
+
+            let half_day = (NANOS_PER_DAY / 2) as i128;
+            burn.sample_at(10_000, consumed(100, 200), TimestampNanos::from(half_day));
+            // 中途充值，余额增加不影响消耗速度
+            burn.sample_at(90_000, consumed(300, 600), TimestampNanos::from(half_day * 2));
+            assert_eq!(burn.samples.len(), 2);
+
+            let report = burn.burn_report().unwrap();
+            assert_eq!(report.from, TimestampNanos::from(half_day));
+            assert_eq!(
+                report.burn_per_day_by_category,
+                vec![("memory".to_string(), 400), ("instructions".to_string(), 800)]
+            );
+            assert_eq!(report.burn_per_day, 1200);
+            assert_eq!(report.runway_days, Some(75));
+            assert!(candid::encode_one(&report).is_ok());
+        }
+    }
+}
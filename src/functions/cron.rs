@@ -0,0 +1,297 @@
+//! 定时表达式
+//!
+//! 支持 5 段 cron 表达式: `分 时 日 月 周`，时间均为 UTC
+//!
+//! * 每段支持 `*`、`1,2,3`、`1-5`、`*/15`、`1-30/5`
+//! * 月份可用 `JAN`-`DEC`，星期可用 `SUN`-`SAT`，`0` 和 `7` 都表示周日
+//! * 星期支持 `MON#1` 表示每月第一个周一
+//! * 日和周同时限制时，任意一个满足即可，只有单独的 `*` 算作不限制，`*/2` 也是限制
+//! * 永远不会触发的表达式 (如 `0 0 30 2 *`) 解析时会被拒绝
+//! * 支持 `@yearly` `@monthly` `@weekly` `@daily` `@hourly` 简写
+
+use std::{fmt::Display, str::FromStr};
+
+use candid::{
+    CandidType,
+    types::{Serializer, Type},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::types::TimestampNanos;
+
+const NANOS_PER_SECOND: i128 = 1_000_000_000;
+const SECONDS_PER_MINUTE: i64 = 60;
+const SECONDS_PER_DAY: i64 = 24 * 60 * 60;
+const MAX_SEARCH_DAYS: i64 = 366 * 8; // 覆盖闰年 2 月 29 日等稀疏规则
+
+const MONTH_NAMES: [&str; 12] = [
+    "JAN", "FEB", "MAR", "APR", "MAY", "JUN", "JUL", "AUG", "SEP", "OCT", "NOV", "DEC",
+];
+const WEEKDAY_NAMES: [&str; 7] = ["SUN", "MON", "TUE", "WED", "THU", "FRI", "SAT"];
+
+/// 解析后的 cron 表达式
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct CronSchedule {
+    expression: String,
+    minutes: u64,
+    hours: u32,
+    days: u32,
+    months: u16,
+    weekdays: u8,
+    nth_weekdays: Vec<(u8, u8)>, // (星期, 第几个)
+    days_restricted: bool,
+    weekdays_restricted: bool,
+}
+
+impl CandidType for CronSchedule {
+    fn _ty() -> Type {
+        String::ty()
+    }
+    fn idl_serialize<S>(&self, serializer: S) -> Result<(), S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_text(&self.expression)
+    }
+}
+
+impl TryFrom<String> for CronSchedule {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl From<CronSchedule> for String {
+    fn from(value: CronSchedule) -> Self {
+        value.expression
+    }
+}
+
+impl Display for CronSchedule {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.expression)
+    }
+}
+
+fn parse_value(value: &str, min: u32, max: u32, names: &[&str], offset: u32) -> Result<u32, String> {
+    let upper = value.to_ascii_uppercase();
+    if let Some(index) = names.iter().position(|name| *name == upper) {
+        return Ok(index as u32 + offset);
+    }
+    let value: u32 = value.parse().map_err(|_| format!("invalid cron value: {value}"))?;
+    if value < min || max < value {
+        return Err(format!("cron value {value} out of range {min}-{max}"));
+    }
+    Ok(value)
+}
+
+// 解析单段，返回位图
+fn parse_field(field: &str, min: u32, max: u32, names: &[&str], offset: u32) -> Result<u64, String> {
+    let mut bits = 0_u64;
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => {
+                let step: u32 = step.parse().map_err(|_| format!("invalid cron step: {step}"))?;
+                if step == 0 {
+                    return Err("cron step can not be 0".to_string());
+                }
+                (range, step)
+            }
+            None => (part, 1),
+        };
+        let (start, end) = if range == "*" {
+            (min, max)
+        } else if let Some((start, end)) = range.split_once('-') {
+            let start = parse_value(start, min, max, names, offset)?;
+            let end = parse_value(end, min, max, names, offset)?;
+            if end < start {
+                return Err(format!("invalid cron range: {range}"));
+            }
+            (start, end)
+        } else {
+            let start = parse_value(range, min, max, names, offset)?;
+            (start, if part.contains('/') { max } else { start })
+        };
+        for value in (start..=end).step_by(step as usize) {
+            bits |= 1 << value;
+        }
+    }
+    Ok(bits)
+}
+
+impl FromStr for CronSchedule {
+    type Err = String;
+
+    fn from_str(expression: &str) -> Result<Self, Self::Err> {
+        let expanded = match expression.trim() {
+            "@yearly" | "@annually" => "0 0 1 1 *",
+            "@monthly" => "0 0 1 * *",
+            "@weekly" => "0 0 * * 0",
+            "@daily" | "@midnight" => "0 0 * * *",
+            "@hourly" => "0 * * * *",
+            expression => expression,
+        };
+        let fields: Vec<&str> = expanded.split_whitespace().collect();
+        let [minute, hour, day, month, weekday] = fields[..] else {
+            return Err(format!("cron expression must have 5 fields: {expression}"));
+        };
+
+        let minutes = parse_field(minute, 0, 59, &[], 0)?;
+        let hours = parse_field(hour, 0, 23, &[], 0)?;
+        let days = parse_field(day, 1, 31, &[], 0)?;
+        let months = parse_field(month, 1, 12, &MONTH_NAMES, 1)?;
+
+        let mut weekdays = 0_u64;
+        let mut nth_weekdays = Vec::new();
+        for part in weekday.split(',') {
+            if let Some((day, nth)) = part.split_once('#') {
+                let day = parse_value(day, 0, 7, &WEEKDAY_NAMES, 0)? % 7;
+                let nth = parse_value(nth, 1, 5, &[], 0)?;
+                nth_weekdays.push((day as u8, nth as u8));
+            } else {
+                weekdays |= parse_field(part, 0, 7, &WEEKDAY_NAMES, 0)?;
+            }
+        }
+        if weekdays & (1 << 7) != 0 {
+            weekdays = (weekdays | 1) & !(1 << 7); // 7 也是周日
+        }
+
+        let cron = CronSchedule {
+            expression: expression.trim().to_string(),
+            minutes,
+            hours: hours as u32,
+            days: days as u32,
+            months: months as u16,
+            weekdays: weekdays as u8,
+            nth_weekdays,
+            days_restricted: day != "*",
+            weekdays_restricted: weekday != "*",
+        };
+        // 任意时间点起的查找范围都覆盖了所有可能的触发时间，找不到则永远不会触发
+        if cron.next_after(TimestampNanos::from(0)).is_none() {
+            return Err(format!("cron expression never fires: {expression}"));
+        }
+        Ok(cron)
+    }
+}
+
+// 天数转换为公历日期 (年, 月, 日)
+// <https://howardhinnant.github.io/date_algorithms.html#civil_from_days>
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+impl CronSchedule {
+    /// 原始表达式
+    pub fn expression(&self) -> &str {
+        &self.expression
+    }
+
+    fn matches_day(&self, days: i64) -> bool {
+        let (_, month, day) = civil_from_days(days);
+        if self.months & (1 << month) == 0 {
+            return false;
+        }
+        let weekday = (days + 4).rem_euclid(7) as u8; // 1970-01-01 是周四
+        let day_matched = self.days & (1 << day) != 0;
+        let weekday_matched = self.weekdays & (1 << weekday) != 0
+            || self
+                .nth_weekdays
+                .iter()
+                .any(|(w, nth)| *w == weekday && (day - 1) / 7 + 1 == u32::from(*nth));
+        match (self.days_restricted, self.weekdays_restricted) {
+            (false, false) => true,
+            (true, false) => day_matched,
+            (false, true) => weekday_matched,
+            (true, true) => day_matched || weekday_matched,
+        }
+    }
+
+    /// 严格晚于指定时间的下一次触发时间，找不到则返回 None
+    pub fn next_after(&self, now: TimestampNanos) -> Option<TimestampNanos> {
+        let seconds = i64::try_from(now.into_inner().div_euclid(NANOS_PER_SECOND)).ok()?;
+        // 从下一分钟开始查找
+        let start = (seconds.div_euclid(SECONDS_PER_MINUTE) + 1) * SECONDS_PER_MINUTE;
+        let start_day = start.div_euclid(SECONDS_PER_DAY);
+        let start_minute = (start.rem_euclid(SECONDS_PER_DAY) / SECONDS_PER_MINUTE) as u32;
+
+        for days in start_day..start_day + MAX_SEARCH_DAYS {
+            if !self.matches_day(days) {
+                continue;
+            }
+            let from = if days == start_day { start_minute } else { 0 };
+            for minute_of_day in from..24 * 60 {
+                let (hour, minute) = (minute_of_day / 60, minute_of_day % 60);
+                if self.hours & (1 << hour) != 0 && self.minutes & (1 << minute) != 0 {
+                    let seconds = days * SECONDS_PER_DAY + i64::from(minute_of_day) * SECONDS_PER_MINUTE;
+                    return Some((i128::from(seconds) * NANOS_PER_SECOND).into());
+                }
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 2024-01-01 00:00:00 UTC 周一
+    const JAN_1_2024: i128 = 1_704_067_200 * NANOS_PER_SECOND;
+    const MINUTE: i128 = 60 * NANOS_PER_SECOND;
+    const DAY: i128 = 24 * 60 * MINUTE;
+
+    fn next(expression: &str, now: i128) -> i128 {
+        let cron: CronSchedule = expression.parse().unwrap();
+        cron.next_after(now.into()).unwrap().into_inner()
+    }
+
+    #[test]
+    fn computes_daily_and_step_schedules() {
+        assert_eq!(next("@daily", JAN_1_2024), JAN_1_2024 + DAY);
+        assert_eq!(next("0 0 * * *", JAN_1_2024 - 1), JAN_1_2024);
+        assert_eq!(next("*/15 * * * *", JAN_1_2024 + MINUTE), JAN_1_2024 + 15 * MINUTE);
+        assert_eq!(
+            next("30 9 * * MON-FRI", JAN_1_2024 + DAY * 4 + 10 * 60 * MINUTE),
+            JAN_1_2024 + DAY * 7 + 9 * 60 * MINUTE + 30 * MINUTE
+        );
+    }
+
+    #[test]
+    fn computes_nth_weekday_of_month() {
+        // 2024-02-05 是二月第一个周一
+        assert_eq!(next("0 0 * * MON#1", JAN_1_2024), JAN_1_2024 + DAY * 35);
+        // 2024-02-29 闰日
+        assert_eq!(next("0 0 29 2 *", JAN_1_2024), JAN_1_2024 + DAY * 59);
+        // */2 也限制了日，和周任意一个满足即可: 2024-01-03 是奇数日
+        assert_eq!(next("0 0 */2 * MON", JAN_1_2024), JAN_1_2024 + DAY * 2);
+        assert_eq!(next("0 0 * * MON", JAN_1_2024), JAN_1_2024 + DAY * 7);
+    }
+
+    #[test]
+    fn rejects_invalid_expressions_and_round_trips_as_text() {
+        assert!("* * * *".parse::<CronSchedule>().is_err());
+        assert!("60 * * * *".parse::<CronSchedule>().is_err());
+        assert!("*/0 * * * *".parse::<CronSchedule>().is_err());
+        assert!("0 0 30 2 *".parse::<CronSchedule>().is_err());
+        assert!("0 0 31 4,6,9,11 *".parse::<CronSchedule>().is_err());
+        assert!("0 0 30 2 MON".parse::<CronSchedule>().is_ok());
+
+        let cron: CronSchedule = "0 12 * * SUN#2".parse().unwrap();
+        let decoded: CronSchedule = candid::decode_one(&candid::encode_one(&cron).unwrap()).unwrap();
+        assert_eq!(decoded, cron);
+        assert_eq!(decoded.to_string(), "0 12 * * SUN#2");
+    }
+}
@@ -0,0 +1,225 @@
+//! 持久化的延时任务
+
+/*
+
+延时任务保存在需要持久化的数据中，升级后重新注册定时器，每个任务只会执行一次
+
+#[ic_cdk::post_upgrade]
+fn post_upgrade() {
+    // 恢复数据后重新注册定时器，升级期间已经到期的任务会立即执行
+    with_state(|s| ic_canister_kit::functions::delay::start_delayed_tasks(&s.delayed, execute_delayed));
+}
+
+fn execute_delayed(id: u64) -> impl Future<Output = ()> {
+    async move {
+        // 先从数据中取出，保证只执行一次
+        let Some(task) = with_mut_state(|s| s.delayed.delay_take(id)) else {
+            return;
+        };
+        match task.name.as_str() {
+            "refund" => refund(task.payload().unwrap()).await,
+            _ => {}
+        }
+    }
+}
+
+#[ic_cdk::update]
+fn refund_later(arg: RefundArg) {
+    let run_at = ...;
+    let id = with_mut_state(|s| s.delayed.delay_push_payload("refund", &arg, run_at)).unwrap();
+    ic_canister_kit::functions::delay::start_delayed_task(id, run_at, execute_delayed);
+}
+
+*/
+
+use std::{cell::RefCell, collections::HashMap};
+
+use crate::{functions::schedule::TimerId, types::TimestampNanos};
+
+thread_local! {
+    static DELAYED_TIMERS: RefCell<HashMap<u64, TimerId>> = RefCell::default(); // 延时任务 id 对应的定时器
+}
+
+// ================== 功能 ==================
+
+/// 延时任务
+pub trait Delayable<Task> {
+    // 查询
+
+    /// 查询所有任务 按执行时间排序
+    fn delay_find_all(&self) -> Vec<&Task>;
+    /// 查询某个任务
+    fn delay_find(&self, id: u64) -> Option<&Task>;
+
+    // 修改
+
+    /// 添加任务，返回任务 id
+    fn delay_push(&mut self, name: String, payload: Vec<u8>, run_at: TimestampNanos) -> u64;
+    /// 取出任务，取出后任务不再存在，用于保证只执行一次
+    fn delay_take(&mut self, id: u64) -> Option<Task>;
+}
+
+/// 注册单个延时任务的定时器，已到期的任务会尽快执行
+///
+/// 定时器触发时只传入任务 id，执行函数应当先通过 [`Delayable::delay_take`] 取出任务再执行
+pub fn start_delayed_task<F>(id: u64, run_at: TimestampNanos, execute: impl FnOnce(u64) -> F + 'static)
+where
+    F: Future<Output = ()> + 'static,
+{
+    stop_delayed_task(id);
+    let delay = u64::try_from(run_at.into_inner() - crate::times::now().into_inner()).unwrap_or_default();
+    let timer_id = ic_cdk_timers::set_timer(std::time::Duration::from_nanos(delay), async move {
+        DELAYED_TIMERS.with_borrow_mut(|timers| timers.remove(&id));
+        execute(id).await;
+    });
+    DELAYED_TIMERS.with_borrow_mut(|timers| timers.insert(id, timer_id));
+}
+
+/// 注册所有延时任务的定时器，升级后需要在 post_upgrade 中调用
+pub fn start_delayed_tasks<F>(tasks: &basic::DelayedTasks, execute: impl Fn(u64) -> F + Clone + 'static)
+where
+    F: Future<Output = ()> + 'static,
+{
+    stop_delayed_tasks();
+    for task in tasks.tasks.values() {
+        start_delayed_task(task.id, task.run_at, execute.clone());
+    }
+}
+
+/// 停止单个延时任务的定时器，不影响持久化的数据
+pub fn stop_delayed_task(id: u64) {
+    crate::functions::schedule::schedule_stop(DELAYED_TIMERS.with_borrow_mut(|timers| timers.remove(&id)));
+}
+
+/// 停止所有延时任务的定时器，不影响持久化的数据
+pub fn stop_delayed_tasks() {
+    for (_, timer_id) in DELAYED_TIMERS.with_borrow_mut(std::mem::take) {
+        crate::functions::schedule::schedule_stop(Some(timer_id));
+    }
+}
+
+// ================== 简单实现 ==================
+
+/// 延时任务简单实现
+pub mod basic {
+    use std::collections::HashMap;
+
+    use candid::CandidType;
+    use serde::{Deserialize, Serialize};
+
+    use crate::{functions::types::Delayable, types::TimestampNanos};
+
+    /// 延时任务
+    #[derive(CandidType, Serialize, Deserialize, Debug, Clone)]
+    pub struct DelayedTask {
+        /// 任务 id
+        pub id: u64,
+        /// 任务名称，用于区分执行方式
+        pub name: String,
+        /// 任务参数 CBOR 编码
+        pub payload: Vec<u8>,
+        /// 创建时间
+        pub created: TimestampNanos,
+        /// 计划执行时间
+        pub run_at: TimestampNanos,
+    }
+
+    impl DelayedTask {
+        /// 解析任务参数
+        pub fn payload<T: 'static + serde::de::DeserializeOwned>(&self) -> Result<T, String> {
+            crate::functions::stable::from_bytes(&self.payload)
+        }
+    }
+
+    /// 持久化的延时任务
+    #[derive(CandidType, Serialize, Deserialize, Debug, Clone, Default)]
+    pub struct DelayedTasks {
+        /// 下一个未使用的 id
+        pub next_id: u64,
+        /// 等待执行的任务
+        pub tasks: HashMap<u64, DelayedTask>,
+    }
+
+    impl DelayedTasks {
+        fn push_at(&mut self, name: String, payload: Vec<u8>, run_at: TimestampNanos, now: TimestampNanos) -> u64 {
+            let id = self.next_id;
+            self.next_id += 1;
+            self.tasks.insert(
+                id,
+                DelayedTask {
+                    id,
+                    name,
+                    payload,
+                    created: now,
+                    run_at,
+                },
+            );
+            id
+        }
+
+        /// 添加任务，参数使用 CBOR 编码
+        pub fn delay_push_payload<T: 'static + Serialize>(
+            &mut self,
+            name: &str,
+            payload: &T,
+            run_at: TimestampNanos,
+        ) -> Result<u64, String> {
+            let payload = crate::functions::stable::to_bytes(payload)?;
+            Ok(self.delay_push(name.to_string(), payload, run_at))
+        }
+    }
+
+    impl Delayable<DelayedTask> for DelayedTasks {
+        // 查询
+        fn delay_find_all(&self) -> Vec<&DelayedTask> {
+            let mut tasks: Vec<&DelayedTask> = self.tasks.values().collect();
+            tasks.sort_by_key(|task| (task.run_at, task.id));
+            tasks
+        }
+        fn delay_find(&self, id: u64) -> Option<&DelayedTask> {
+            self.tasks.get(&id)
+        }
+
+        // 修改
+        fn delay_push(&mut self, name: String, payload: Vec<u8>, run_at: TimestampNanos) -> u64 {
+            self.push_at(name, payload, run_at, crate::times::now())
+        }
+        fn delay_take(&mut self, id: u64) -> Option<DelayedTask> {
+            self.tasks.remove(&id)
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::DelayedTasks;
+        use crate::{functions::types::Delayable, types::TimestampNanos};
+
+        #[test]
+        fn tasks_survive_serialization_and_are_taken_only_once() {
+            let mut tasks = DelayedTasks::default();
+            let payload = crate::functions::stable::to_bytes(&(7_u64, "refund".to_string())).unwrap();
+            let late = tasks.push_at(
+                "refund".to_string(),
+                payload,
+                TimestampNanos::from(20),
+                TimestampNanos::from(0),
+            );
+            let early = tasks.push_at(
+                "noop".to_string(),
+                vec![],
+                TimestampNanos::from(10),
+                TimestampNanos::from(0),
+            );
+
+            let bytes = crate::functions::stable::to_bytes(&tasks).unwrap();
+            let mut restored: DelayedTasks = crate::functions::stable::from_bytes(&bytes).unwrap();
+            let ids: Vec<u64> = restored.delay_find_all().iter().map(|task| task.id).collect();
+            assert_eq!(ids, vec![early, late]);
+
+            let task = restored.delay_take(late).unwrap();
+            assert_eq!(task.payload::<(u64, String)>().unwrap(), (7, "refund".to_string()));
+            assert!(restored.delay_take(late).is_none());
+            assert_eq!(restored.next_id, 2);
+        }
+    }
+}
@@ -0,0 +1,558 @@
+//! 子罐子集群管理
+
+/*
+
+每个客户一个子罐子，注册表保存在需要持久化的数据中，升级时分批灰度
+
+#[ic_cdk::update]
+async fn create_child(arg: CreateArg) -> Result<CanisterId, String> {
+    let canister_id = deploy_canister(None, CYCLES, WASM.to_vec(), arg.init).await.map_err(|e| e.to_string())?;
+    with_mut_state(|s| s.fleet.fleet_insert(canister_id, wasm_module_hash(WASM), VERSION.to_string()))?;
+    Ok(canister_id)
+}
+
+#[ic_cdk::update]
+async fn rollout(version: String, canaries: Vec<CanisterId>) -> Result<(), String> {
+    // 引用仓库中登记的版本
+    let version: WasmVersion = version.parse()?;
+    let release = with_state(|s| s.wasm.wasm_find(&version).cloned()).ok_or("unknown version")?;
+    with_mut_state(|s| s.fleet.fleet_rollout_start_release(&release, canaries, 10))?;
+    loop {
+        // 金丝雀全部成功后才会继续，任意失败则暂停
+        let batch = with_mut_state(|s| s.fleet.fleet_rollout_next());
+        if batch.is_empty() {
+            return Ok(());
+        }
+        let results = ic_canister_kit::functions::fleet::upgrade_fleet_children(&batch, &release, None).await;
+        with_mut_state(|s| results.into_iter().for_each(|(id, result)| s.fleet.fleet_rollout_done(id, result)));
+    }
+}
+
+#[ic_cdk::update]
+fn wasm_delete(version: String) -> Result<(), String> {
+    // 正在升级的目标版本不能删除
+    let version: WasmVersion = version.parse()?;
+    with_mut_state(|s| s.wasm.wasm_delete(&version, s.fleet.fleet_rollout_target().as_slice()).map(|_| ()))
+}
+
+*/
+
+use crate::{
+    canister::{
+        codes::{CanisterCodeHash, CanisterInitArg},
+        types::CanisterInstallMode,
+    },
+    functions::types::WasmRelease,
+    identity::CanisterId,
+};
+
+// ================== 功能 ==================
+
+/// 子罐子集群
+pub trait Fleetable<Child, Rollout> {
+    // 查询
+
+    /// 查询所有子罐子
+    fn fleet_find_all(&self) -> Vec<&Child>;
+    /// 查询某个子罐子
+    fn fleet_find(&self, canister_id: &CanisterId) -> Option<&Child>;
+    /// 当前或最近一次的升级
+    fn fleet_rollout(&self) -> Option<&Rollout>;
+
+    // 修改
+
+    /// 登记新建的子罐子
+    fn fleet_insert(
+        &mut self,
+        canister_id: CanisterId,
+        wasm_module_hash: CanisterCodeHash,
+        version: String,
+    ) -> Result<(), String>;
+    /// 移除子罐子，不会删除罐子本身
+    fn fleet_remove(&mut self, canister_id: &CanisterId) -> Option<Child>;
+
+    /// 开始升级，先升级金丝雀，全部成功后再升级其余子罐子
+    fn fleet_rollout_start(
+        &mut self,
+        wasm_module_hash: CanisterCodeHash,
+        version: String,
+        canaries: Vec<CanisterId>,
+        concurrency: u32,
+    ) -> Result<(), String>;
+    /// 取出下一批需要升级的子罐子，升级暂停或者没有可以升级的子罐子时返回空
+    fn fleet_rollout_next(&mut self) -> Vec<CanisterId>;
+    /// 记录升级结果，任意失败会暂停升级
+    fn fleet_rollout_done(&mut self, canister_id: CanisterId, result: Result<(), String>);
+    /// 继续暂停或被打断的升级，失败的和仍在升级中的子罐子会重新排队
+    /// ! 只能在没有升级调用正在执行时调用，例如罐子升级后
+    fn fleet_rollout_resume(&mut self) -> Result<(), String>;
+    /// 取消未完成的升级，升级中的子罐子会标记为失败
+    fn fleet_rollout_cancel(&mut self) -> Result<(), String>;
+}
+
+/// 并发升级一批子罐子，返回每个子罐子的结果
+///
+/// 升级前校验模块内容和版本登记的 hash，超过 [`crate::canister::chunks::MAX_CHUNK_SIZE_IN_BYTES`]
+/// 的模块通过子罐子自己的 chunk store 安装，安装后清空
+pub async fn upgrade_fleet_children(
+    children: &[CanisterId],
+    release: &WasmRelease,
+    arg: Option<CanisterInitArg>,
+) -> Vec<(CanisterId, Result<(), String>)> {
+    let wasm_module = &release.wasm_module;
+    if crate::canister::codes::wasm_module_hash(wasm_module) != release.wasm_module_hash {
+        let message = format!("version {} wasm module hash mismatch", release.version);
+        return children.iter().map(|id| (*id, Err(message.clone()))).collect();
+    }
+    let chunked = crate::canister::chunks::MAX_CHUNK_SIZE_IN_BYTES < wasm_module.len();
+    let futures = children.iter().map(|canister_id| {
+        let arg = arg.clone();
+        async move {
+            let result = if chunked {
+                crate::canister::deploy::deploy_chunked_code(
+                    *canister_id,
+                    CanisterInstallMode::Upgrade(None),
+                    None,
+                    wasm_module,
+                    arg.unwrap_or_default(),
+                    true,
+                )
+                .await
+                .map(|_| ())
+            } else {
+                crate::canister::codes::upgrade_code(*canister_id, wasm_module.clone(), arg, None).await
+            };
+            (*canister_id, result.map_err(|err| err.to_string()))
+        }
+    });
+    crate::canister::join_all(futures.collect()).await
+}
+
+// ================== 简单实现 ==================
+
+/// 子罐子集群简单实现
+pub mod basic {
+    use std::collections::BTreeMap;
+
+    use candid::CandidType;
+    use serde::{Deserialize, Serialize};
+
+    use crate::{
+        canister::codes::CanisterCodeHash,
+        functions::types::{Fleetable, WasmRelease},
+        identity::CanisterId,
+        types::TimestampNanos,
+    };
+
+    /// 子罐子状态
+    #[derive(CandidType, Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+    pub enum FleetChildStatus {
+        /// 正常运行
+        Running,
+        /// 升级中
+        Upgrading,
+        /// 最近一次升级失败
+        Failed(String),
+    }
+
+    /// 子罐子
+    #[derive(CandidType, Serialize, Deserialize, Debug, Clone)]
+    pub struct FleetChild {
+        /// 罐子 id
+        pub canister_id: CanisterId,
+        /// 登记时间
+        pub created: TimestampNanos,
+        /// 当前代码 hash
+        pub wasm_module_hash: CanisterCodeHash,
+        /// 当前版本
+        pub version: String,
+        /// 状态
+        pub status: FleetChildStatus,
+        /// 状态更新时间
+        pub updated: TimestampNanos,
+    }
+
+    /// 分批升级
+    #[derive(CandidType, Serialize, Deserialize, Debug, Clone)]
+    pub struct FleetRollout {
+        /// 目标代码 hash
+        pub wasm_module_hash: CanisterCodeHash,
+        /// 目标版本
+        pub version: String,
+        /// 同时升级的最大数量
+        pub concurrency: u32,
+        /// 是否仍在金丝雀阶段
+        pub canary_stage: bool,
+        /// 等待升级的金丝雀
+        pub canaries: Vec<CanisterId>,
+        /// 等待升级的其余子罐子
+        pub pending: Vec<CanisterId>,
+        /// 升级中
+        pub in_flight: Vec<CanisterId>,
+        /// 升级成功
+        pub succeeded: Vec<CanisterId>,
+        /// 升级失败及原因
+        pub failed: Vec<(CanisterId, String)>,
+        /// 是否因失败暂停
+        pub halted: bool,
+        /// 是否已取消
+        #[serde(default)]
+        pub cancelled: bool,
+        /// 开始时间
+        pub started: TimestampNanos,
+        /// 完成时间
+        pub finished: Option<TimestampNanos>,
+    }
+
+    impl FleetRollout {
+        /// 是否还有未完成的升级
+        pub fn is_active(&self) -> bool {
+            self.finished.is_none()
+        }
+    }
+
+    /// 持久化的子罐子集群
+    #[derive(CandidType, Serialize, Deserialize, Debug, Clone, Default)]
+    pub struct Fleet {
+        /// 子罐子
+        pub children: BTreeMap<CanisterId, FleetChild>,
+        /// 当前或最近一次的升级
+        pub rollout: Option<FleetRollout>,
+    }
+
+    impl Fleet {
+        fn set_status(&mut self, canister_id: &CanisterId, status: FleetChildStatus, now: TimestampNanos) {
+            if let Some(child) = self.children.get_mut(canister_id) {
+                child.status = status;
+                child.updated = now;
+            }
+        }
+
+        fn insert_at(
+            &mut self,
+            canister_id: CanisterId,
+            wasm_module_hash: CanisterCodeHash,
+            version: String,
+            now: TimestampNanos,
+        ) -> Result<(), String> {
+            if self.children.contains_key(&canister_id) {
+                return Err(format!("canister {} already exists", canister_id.to_text()));
+            }
+            self.children.insert(
+                canister_id,
+                FleetChild {
+                    canister_id,
+                    created: now,
+                    wasm_module_hash,
+                    version,
+                    status: FleetChildStatus::Running,
+                    updated: now,
+                },
+            );
+            Ok(())
+        }
+
+        fn rollout_start_at(
+            &mut self,
+            wasm_module_hash: CanisterCodeHash,
+            version: String,
+            canaries: Vec<CanisterId>,
+            concurrency: u32,
+            now: TimestampNanos,
+        ) -> Result<(), String> {
+            if self.rollout.as_ref().is_some_and(FleetRollout::is_active) {
+                return Err("another rollout is in progress".to_string());
+            }
+            if concurrency == 0 {
+                return Err("concurrency can not be 0".to_string());
+            }
+            if let Some(canister_id) = canaries.iter().find(|id| !self.children.contains_key(id)) {
+                return Err(format!("canary {} is not a fleet child", canister_id.to_text()));
+            }
+
+            let pending = self
+                .children
+                .values()
+                .filter(|child| child.wasm_module_hash != wasm_module_hash && !canaries.contains(&child.canister_id))
+                .map(|child| child.canister_id)
+                .collect();
+            self.rollout = Some(FleetRollout {
+                wasm_module_hash,
+                version,
+                concurrency,
+                canary_stage: !canaries.is_empty(),
+                canaries,
+                pending,
+                in_flight: Vec::new(),
+                succeeded: Vec::new(),
+                failed: Vec::new(),
+                halted: false,
+                cancelled: false,
+                started: now,
+                finished: None,
+            });
+            self.check_finished(now);
+            Ok(())
+        }
+
+        /// 未完成的升级的目标代码 hash
+        pub fn fleet_rollout_target(&self) -> Option<&CanisterCodeHash> {
+            self.rollout
+                .as_ref()
+                .filter(|rollout| rollout.is_active())
+                .map(|rollout| &rollout.wasm_module_hash)
+        }
+
+        /// 以仓库中的版本为目标开始升级
+        pub fn fleet_rollout_start_release(
+            &mut self,
+            release: &WasmRelease,
+            canaries: Vec<CanisterId>,
+            concurrency: u32,
+        ) -> Result<(), String> {
+            self.fleet_rollout_start(
+                release.wasm_module_hash.clone(),
+                release.version.to_string(),
+                canaries,
+                concurrency,
+            )
+        }
+
+        fn rollout_next_at(&mut self, now: TimestampNanos) -> Vec<CanisterId> {
+            let Some(rollout) = self
+                .rollout
+                .as_mut()
+                .filter(|rollout| rollout.is_active() && !rollout.halted)
+            else {
+                return Vec::new();
+            };
+            if rollout.canary_stage && rollout.canaries.is_empty() && rollout.in_flight.is_empty() {
+                rollout.canary_stage = false;
+            }
+            let capacity = (rollout.concurrency as usize).saturating_sub(rollout.in_flight.len());
+            let source = if rollout.canary_stage {
+                &mut rollout.canaries
+            } else {
+                &mut rollout.pending
+            };
+            let batch: Vec<CanisterId> = source.drain(..capacity.min(source.len())).collect();
+            rollout.in_flight.extend(batch.iter().copied());
+            for canister_id in &batch {
+                self.set_status(canister_id, FleetChildStatus::Upgrading, now);
+            }
+            batch
+        }
+
+        fn rollout_done_at(&mut self, canister_id: CanisterId, result: Result<(), String>, now: TimestampNanos) {
+            let Some(rollout) = self.rollout.as_mut() else {
+                return;
+            };
+            let Some(index) = rollout.in_flight.iter().position(|id| *id == canister_id) else {
+                return;
+            };
+            rollout.in_flight.remove(index);
+            match result {
+                Ok(()) => {
+                    rollout.succeeded.push(canister_id);
+                    let (wasm_module_hash, version) = (rollout.wasm_module_hash.clone(), rollout.version.clone());
+                    if let Some(child) = self.children.get_mut(&canister_id) {
+                        child.wasm_module_hash = wasm_module_hash;
+                        child.version = version;
+                    }
+                    self.set_status(&canister_id, FleetChildStatus::Running, now);
+                }
+                Err(message) => {
+                    rollout.failed.push((canister_id, message.clone()));
+                    rollout.halted = true;
+                    self.set_status(&canister_id, FleetChildStatus::Failed(message), now);
+                }
+            }
+            self.check_finished(now);
+        }
+
+        fn rollout_resume_at(&mut self, now: TimestampNanos) -> Result<(), String> {
+            let Some(rollout) = self.rollout.as_mut().filter(|rollout| rollout.is_active()) else {
+                return Err("no rollout in progress".to_string());
+            };
+            let canary_stage = rollout.canary_stage;
+            let stale = std::mem::take(&mut rollout.in_flight);
+            let failed = std::mem::take(&mut rollout.failed)
+                .into_iter()
+                .map(|(canister_id, _)| canister_id);
+            let requeued: Vec<CanisterId> = stale.iter().copied().chain(failed).collect();
+            if canary_stage {
+                rollout.canaries.extend(requeued);
+            } else {
+                rollout.pending.extend(requeued);
+            }
+            rollout.halted = false;
+            // 被打断的子罐子结果未知，恢复为运行状态后重新升级
+            for canister_id in stale {
+                self.set_status(&canister_id, FleetChildStatus::Running, now);
+            }
+            self.check_finished(now);
+            Ok(())
+        }
+
+        fn rollout_cancel_at(&mut self, now: TimestampNanos) -> Result<(), String> {
+            let Some(rollout) = self.rollout.as_mut().filter(|rollout| rollout.is_active()) else {
+                return Err("no rollout in progress".to_string());
+            };
+            rollout.cancelled = true;
+            rollout.finished = Some(now);
+            rollout.canaries.clear();
+            rollout.pending.clear();
+            let in_flight = std::mem::take(&mut rollout.in_flight);
+            let message = "rollout cancelled".to_string();
+            rollout
+                .failed
+                .extend(in_flight.iter().map(|canister_id| (*canister_id, message.clone())));
+            for canister_id in in_flight {
+                self.set_status(&canister_id, FleetChildStatus::Failed(message.clone()), now);
+            }
+            Ok(())
+        }
+
+        // 没有等待和执行中的子罐子，并且没有失败，则升级完成
+        fn check_finished(&mut self, now: TimestampNanos) {
+            if let Some(rollout) = self.rollout.as_mut()
+                && rollout.canaries.is_empty()
+                && rollout.pending.is_empty()
+                && rollout.in_flight.is_empty()
+                && rollout.failed.is_empty()
+            {
+                rollout.canary_stage = false;
+                rollout.finished.get_or_insert(now);
+            }
+        }
+    }
+
+    impl Fleetable<FleetChild, FleetRollout> for Fleet {
+        // 查询
+        fn fleet_find_all(&self) -> Vec<&FleetChild> {
+            self.children.values().collect()
+        }
+        fn fleet_find(&self, canister_id: &CanisterId) -> Option<&FleetChild> {
+            self.children.get(canister_id)
+        }
+        fn fleet_rollout(&self) -> Option<&FleetRollout> {
+            self.rollout.as_ref()
+        }
+
+        // 修改
+        fn fleet_insert(
+            &mut self,
+            canister_id: CanisterId,
+            wasm_module_hash: CanisterCodeHash,
+            version: String,
+        ) -> Result<(), String> {
+            self.insert_at(canister_id, wasm_module_hash, version, crate::times::now())
+        }
+        fn fleet_remove(&mut self, canister_id: &CanisterId) -> Option<FleetChild> {
+            self.children.remove(canister_id)
+        }
+
+        fn fleet_rollout_start(
+            &mut self,
+            wasm_module_hash: CanisterCodeHash,
+            version: String,
+            canaries: Vec<CanisterId>,
+            concurrency: u32,
+        ) -> Result<(), String> {
+            self.rollout_start_at(wasm_module_hash, version, canaries, concurrency, crate::times::now())
+        }
+        fn fleet_rollout_next(&mut self) -> Vec<CanisterId> {
+            self.rollout_next_at(crate::times::now())
+        }
+        fn fleet_rollout_done(&mut self, canister_id: CanisterId, result: Result<(), String>) {
+            self.rollout_done_at(canister_id, result, crate::times::now());
+        }
+        fn fleet_rollout_resume(&mut self) -> Result<(), String> {
+            self.rollout_resume_at(crate::times::now())
+        }
+        fn fleet_rollout_cancel(&mut self) -> Result<(), String> {
+            self.rollout_cancel_at(crate::times::now())
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use candid::Principal;
+
+        use super::{Fleet, FleetChildStatus};
+        use crate::types::TimestampNanos;
+
+        fn id(n: u8) -> Principal {
+            Principal::from_slice(&[n])
+        }
+
+        #[test]
+        fn rolls_out_canaries_first_and_halts_on_failure() {
+            let now = TimestampNanos::from(0);
+            let mut fleet = Fleet::default();
+            for n in 1..=5 {
+                fleet.insert_at(id(n), vec![1], "1.0.0".to_string(), now).unwrap();
+            }
+            assert!(fleet.insert_at(id(1), vec![1], "1.0.0".to_string(), now).is_err());
+
+            fleet
+                .rollout_start_at(vec![2], "2.0.0".to_string(), vec![id(3)], 2, now)
+                .unwrap();
+            assert!(
+                fleet
+                    .rollout_start_at(vec![2], "2.0.0".to_string(), vec![], 2, now)
+                    .is_err()
+            );
+
+            // 金丝雀阶段只升级金丝雀
+            assert_eq!(fleet.rollout_next_at(now), vec![id(3)]);
+            assert!(fleet.rollout_next_at(now).is_empty());
+            fleet.rollout_done_at(id(3), Ok(()), now);
+            assert_eq!(fleet.children[&id(3)].version, "2.0.0");
+
+            assert_eq!(fleet.rollout_next_at(now), vec![id(1), id(2)]);
+            assert_eq!(fleet.children[&id(1)].status, FleetChildStatus::Upgrading);
+            fleet.rollout_done_at(id(1), Err("trapped".to_string()), now);
+            fleet.rollout_done_at(id(2), Ok(()), now);
+            assert!(fleet.rollout_next_at(now).is_empty());
+            assert_eq!(
+                fleet.children[&id(1)].status,
+                FleetChildStatus::Failed("trapped".to_string())
+            );
+
+            fleet.rollout_resume_at(now).unwrap();
+            let mut batch = fleet.rollout_next_at(now);
+            batch.sort();
+            assert_eq!(batch, vec![id(4), id(5)]);
+            for canister_id in batch {
+                fleet.rollout_done_at(canister_id, Ok(()), now);
+            }
+            assert_eq!(fleet.rollout_next_at(now), vec![id(1)]);
+            fleet.rollout_done_at(id(1), Ok(()), TimestampNanos::from(9));
+
+            let rollout = fleet.rollout.as_ref().unwrap();
+            assert_eq!(rollout.finished, Some(TimestampNanos::from(9)));
+            assert!(fleet.children.values().all(|child| child.wasm_module_hash == vec![2]));
+
+            // 升级中被打断的子罐子重新排队，或者取消
+            fleet
+                .rollout_start_at(vec![3], "3.0.0".to_string(), vec![], 2, now)
+                .unwrap();
+            assert_eq!(fleet.rollout_next_at(now).len(), 2);
+            fleet.rollout_resume_at(now).unwrap();
+            assert_eq!(fleet.children[&id(1)].status, FleetChildStatus::Running);
+            assert_eq!(fleet.rollout_next_at(now).len(), 2);
+            fleet.rollout_cancel_at(now).unwrap();
+            assert!(fleet.fleet_rollout_target().is_none());
+            assert!(
+                fleet
+                    .rollout_start_at(vec![3], "3.0.0".to_string(), vec![], 2, now)
+                    .is_ok()
+            );
+
+            let bytes = crate::functions::stable::to_bytes(&fleet).unwrap();
+            let restored: Fleet = crate::functions::stable::from_bytes(&bytes).unwrap();
+            assert_eq!(restored.children.len(), 5);
+        }
+    }
+}
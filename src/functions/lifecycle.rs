@@ -0,0 +1,559 @@
+//! 受管罐子的生命周期
+
+/*
+
+每个受管罐子的状态保存在需要持久化的数据中，状态变更前先校验，失败的调用可以重试
+
+#[ic_cdk::update]
+async fn delete_child(id: u64) -> Result<(), String> {
+    use CanisterLifecycleState::*;
+    // 1. 运行状态下先取回剩余 cycles，失败则停留在取回中，可以重试
+    let canister_id = with_mut_state(|s| s.lifecycle.lifecycle_transit(id, Withdrawing))?;
+    if let Err(err) = withdraw_managed_canister_cycles(canister_id, self_canister_id()).await {
+        with_mut_state(|s| s.lifecycle.lifecycle_failed(id, err.to_string()))?;
+        return Err(err.to_string());
+    }
+    // 2. 停止，失败则由定时器按退避时间重试，停止后再次调用即可继续删除
+    with_mut_state(|s| s.lifecycle.lifecycle_transit(id, Stopping))?;
+    if let Err(err) = stop_canister_once(canister_id).await {
+        with_mut_state(|s| s.lifecycle.lifecycle_failed(id, err.to_string()))?;
+        start_stop_managed_canister(id, 5, Duration::from_secs(1), |f| with_mut_state(|s| f(&mut s.lifecycle)));
+        return Err(err.to_string());
+    }
+    with_mut_state(|s| s.lifecycle.lifecycle_transit(id, Stopped))?;
+    // 3. 删除，只有取回过 cycles 的罐子才能进入删除中
+    with_mut_state(|s| s.lifecycle.lifecycle_transit(id, Deleting))?;
+    if let Err(err) = delete_canister(canister_id).await {
+        with_mut_state(|s| s.lifecycle.lifecycle_failed(id, err.to_string()))?;
+        return Err(err.to_string());
+    }
+    with_mut_state(|s| s.lifecycle.lifecycle_transit(id, Deleted)).map(|_| ())
+}
+
+#[ic_cdk::post_upgrade]
+fn post_upgrade() {
+    // 升级会打断正在进行的状态变更，用定时器逐步继续，直到不再是中间状态或者失败
+    ic_cdk_timers::set_timer(Duration::ZERO, async {
+        use CanisterLifecycleState::*;
+        let interrupted = with_state(|s| s.lifecycle.lifecycle_interrupted());
+        for (id, canister_id, mut state) in interrupted {
+            while state.is_transitional() {
+                let result = resume_managed_canister(canister_id, state, self_canister_id()).await;
+                let next = with_mut_state(|s| s.lifecycle.lifecycle_resumed(id, result.map_err(|e| e.to_string())));
+                if next == state {
+                    break;
+                }
+                state = next;
+            }
+            if state == Stopping {
+                // 停止失败的交给定时器重试
+                start_stop_managed_canister(id, 5, Duration::from_secs(1), |f| with_mut_state(|s| f(&mut s.lifecycle)));
+            }
+        }
+    });
+}
+
+*/
+
+use candid::CandidType;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    canister::types::{CanisterCallResult, CanisterStatusType},
+    identity::CanisterId,
+    types::DurationNanos,
+};
+
+/// 受管罐子取回 cycles 的接口
+/// ! 受管罐子必须实现 withdraw_cycles : (principal) -> (nat) 接口，把剩余 cycles 充值给参数罐子
+pub const LIFECYCLE_WITHDRAW_CYCLES_METHOD: &str = "withdraw_cycles";
+
+// ================== 功能 ==================
+
+/// 受管罐子的生命周期状态
+#[derive(CandidType, Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum CanisterLifecycleState {
+    /// 创建中，还没有罐子 id
+    Creating,
+    /// 安装代码中
+    Installing,
+    /// 运行中
+    Running,
+    /// 取回 cycles 中，罐子仍在运行，删除前必须经过
+    Withdrawing,
+    /// 停止中
+    Stopping,
+    /// 已停止
+    Stopped,
+    /// 删除中
+    Deleting,
+    /// 已删除
+    Deleted,
+}
+
+impl CanisterLifecycleState {
+    /// 是否可以变更到目标状态
+    pub fn can_transit(&self, to: &CanisterLifecycleState) -> bool {
+        use CanisterLifecycleState::*;
+        matches!(
+            (self, to),
+            (Creating, Installing)
+                | (Installing, Running | Stopped)
+                | (Running, Installing | Stopping | Withdrawing)
+                | (Withdrawing, Stopping | Running)
+                | (Stopping, Stopped | Running)
+                | (Stopped, Running | Installing | Deleting)
+                | (Deleting, Deleted | Stopped)
+        )
+    }
+
+    /// 是否是中间状态，升级打断后需要继续
+    pub fn is_transitional(&self) -> bool {
+        matches!(
+            self,
+            CanisterLifecycleState::Creating
+                | CanisterLifecycleState::Installing
+                | CanisterLifecycleState::Withdrawing
+                | CanisterLifecycleState::Stopping
+                | CanisterLifecycleState::Deleting
+        )
+    }
+}
+
+/// 受管罐子生命周期
+pub trait CanisterLifecyclable<Managed> {
+    // 查询
+
+    /// 查询所有受管罐子
+    fn lifecycle_find_all(&self) -> Vec<&Managed>;
+    /// 查询某个受管罐子
+    fn lifecycle_find(&self, id: u64) -> Option<&Managed>;
+    /// 根据罐子 id 查询
+    fn lifecycle_find_by_canister(&self, canister_id: &CanisterId) -> Option<&Managed>;
+    /// 处于中间状态的受管罐子，升级后需要继续
+    fn lifecycle_interrupted(&self) -> Vec<(u64, Option<CanisterId>, CanisterLifecycleState)>;
+    /// 停止时间超过 timeout 的受管罐子
+    fn lifecycle_stuck(&self, timeout: DurationNanos) -> Vec<(u64, CanisterId)>;
+
+    // 修改
+
+    /// 开始创建，返回本地 id
+    fn lifecycle_create(&mut self) -> u64;
+    /// 创建成功，进入安装状态
+    fn lifecycle_created(&mut self, id: u64, canister_id: CanisterId) -> Result<(), String>;
+    /// 变更状态，返回罐子 id，没有取回过 cycles 的罐子不能进入删除中
+    fn lifecycle_transit(&mut self, id: u64, state: CanisterLifecycleState) -> Result<CanisterId, String>;
+    /// 记录当前状态下的失败，状态不变
+    fn lifecycle_failed(&mut self, id: u64, error: String) -> Result<(), String>;
+    /// 记录继续的结果，返回记录后的状态
+    fn lifecycle_resumed(&mut self, id: u64, result: Result<CanisterLifecycleState, String>) -> CanisterLifecycleState;
+    /// 移除受管罐子，不会删除罐子本身
+    fn lifecycle_remove(&mut self, id: u64) -> Option<Managed>;
+}
+
+/// 尝试停止罐子一次，失败则检查状态，已经停止也算成功
+///
+/// 卡在停止中的罐子会先启动，取消卡住的停止，下次尝试时重新停止。
+/// 需要重试时用 [`basic::start_stop_managed_canister`] 为每次尝试注册新的定时器，不要在同一个任务中等待
+pub async fn stop_canister_once(canister_id: CanisterId) -> CanisterCallResult<()> {
+    let Err(err) = crate::canister::life::stop_canister(canister_id).await else {
+        return Ok(());
+    };
+    match crate::canister::status::canister_status(canister_id)
+        .await
+        .map(|s| s.status)
+    {
+        Ok(CanisterStatusType::Stopped) => Ok(()),
+        Ok(CanisterStatusType::Stopping) => {
+            crate::canister::life::start_canister(canister_id).await?; // 取消卡住的停止
+            Err(err)
+        }
+        _ => Err(err),
+    }
+}
+
+/// 取回受管罐子的剩余 cycles，受管罐子需要处于运行状态
+pub async fn withdraw_managed_canister_cycles(
+    canister_id: CanisterId,
+    beneficiary: CanisterId,
+) -> CanisterCallResult<candid::Nat> {
+    crate::canister::call::call_canister(canister_id, LIFECYCLE_WITHDRAW_CYCLES_METHOD, (beneficiary,)).await
+}
+
+/// 继续被打断的状态变更，每次只前进一步，返回变更后的状态
+///
+/// 取回中会把 cycles 取回到 `beneficiary`；停止中只尝试一次；创建中和安装中需要代码，原样返回，由调用方处理
+pub async fn resume_managed_canister(
+    canister_id: Option<CanisterId>,
+    state: CanisterLifecycleState,
+    beneficiary: CanisterId,
+) -> CanisterCallResult<CanisterLifecycleState> {
+    let Some(canister_id) = canister_id else {
+        return Ok(state);
+    };
+    match state {
+        CanisterLifecycleState::Withdrawing => {
+            withdraw_managed_canister_cycles(canister_id, beneficiary).await?;
+            Ok(CanisterLifecycleState::Stopping)
+        }
+        CanisterLifecycleState::Stopping => {
+            stop_canister_once(canister_id).await?;
+            Ok(CanisterLifecycleState::Stopped)
+        }
+        CanisterLifecycleState::Deleting => {
+            crate::canister::life::delete_canister(canister_id).await?;
+            Ok(CanisterLifecycleState::Deleted)
+        }
+        state => Ok(state),
+    }
+}
+
+// ================== 简单实现 ==================
+
+/// 受管罐子生命周期简单实现
+pub mod basic {
+    use std::collections::BTreeMap;
+
+    use candid::CandidType;
+    use serde::{Deserialize, Serialize};
+
+    use super::CanisterLifecycleState;
+    use crate::{
+        functions::types::CanisterLifecyclable,
+        identity::CanisterId,
+        types::{DurationNanos, TimestampNanos},
+    };
+
+    /// 受管罐子
+    #[derive(CandidType, Serialize, Deserialize, Debug, Clone)]
+    pub struct ManagedCanister {
+        /// 本地 id
+        pub id: u64,
+        /// 罐子 id，创建成功后才有
+        pub canister_id: Option<CanisterId>,
+        /// 当前状态
+        pub state: CanisterLifecycleState,
+        /// 进入当前状态的时间
+        pub since: TimestampNanos,
+        /// 当前状态下失败的次数，停止中即为已经失败的停止尝试次数
+        pub failures: u32,
+        /// 最近一次失败的原因
+        pub error: Option<String>,
+        /// 是否已经取回 cycles，重新运行后清除
+        #[serde(default)]
+        pub cycles_withdrawn: bool,
+        /// 登记时间
+        pub created: TimestampNanos,
+    }
+
+    /// 持久化的受管罐子
+    #[derive(CandidType, Serialize, Deserialize, Debug, Clone, Default)]
+    pub struct ManagedCanisters {
+        next_id: u64,
+        /// 受管罐子
+        pub canisters: BTreeMap<u64, ManagedCanister>,
+    }
+
+    impl ManagedCanisters {
+        fn get_mut(&mut self, id: u64) -> Result<&mut ManagedCanister, String> {
+            self.canisters
+                .get_mut(&id)
+                .ok_or_else(|| format!("managed canister {id} not found"))
+        }
+
+        fn create_at(&mut self, now: TimestampNanos) -> u64 {
+            let id = self.next_id;
+            self.next_id += 1;
+            self.canisters.insert(
+                id,
+                ManagedCanister {
+                    id,
+                    canister_id: None,
+                    state: CanisterLifecycleState::Creating,
+                    since: now,
+                    failures: 0,
+                    error: None,
+                    cycles_withdrawn: false,
+                    created: now,
+                },
+            );
+            id
+        }
+
+        fn created_at(&mut self, id: u64, canister_id: CanisterId, now: TimestampNanos) -> Result<(), String> {
+            let managed = self.get_mut(id)?;
+            if managed.canister_id.is_some() {
+                return Err(format!("managed canister {id} is already created"));
+            }
+            managed.canister_id = Some(canister_id);
+            self.transit_at(id, CanisterLifecycleState::Installing, now).map(|_| ())
+        }
+
+        fn transit_at(
+            &mut self,
+            id: u64,
+            state: CanisterLifecycleState,
+            now: TimestampNanos,
+        ) -> Result<CanisterId, String> {
+            let managed = self.get_mut(id)?;
+            let canister_id = managed
+                .canister_id
+                .ok_or_else(|| format!("managed canister {id} is not created"))?;
+            if !managed.state.can_transit(&state) {
+                return Err(format!(
+                    "managed canister {id} can not transit from {:?} to {state:?}",
+                    managed.state
+                ));
+            }
+            match (managed.state, state) {
+                (_, CanisterLifecycleState::Deleting) if !managed.cycles_withdrawn => {
+                    return Err(format!("managed canister {id} must withdraw cycles before deleting"));
+                }
+                (CanisterLifecycleState::Withdrawing, CanisterLifecycleState::Stopping) => {
+                    managed.cycles_withdrawn = true
+                }
+                (_, CanisterLifecycleState::Running | CanisterLifecycleState::Installing) => {
+                    managed.cycles_withdrawn = false
+                }
+                _ => {}
+            }
+            managed.state = state;
+            managed.since = now;
+            managed.failures = 0;
+            managed.error = None;
+            Ok(canister_id)
+        }
+
+        fn failed(&mut self, id: u64, error: String) -> Result<(), String> {
+            let managed = self.get_mut(id)?;
+            managed.failures += 1;
+            managed.error = Some(error);
+            Ok(())
+        }
+
+        fn resumed_at(
+            &mut self,
+            id: u64,
+            result: Result<CanisterLifecycleState, String>,
+            now: TimestampNanos,
+        ) -> CanisterLifecycleState {
+            let Some(current) = self.canisters.get(&id).map(|managed| managed.state) else {
+                return CanisterLifecycleState::Deleted;
+            };
+            let error = match result {
+                Ok(state) if state == current => return current,
+                Ok(state) => match self.transit_at(id, state, now) {
+                    Ok(_) => return state,
+                    Err(error) => error,
+                },
+                Err(error) => error,
+            };
+            let _ = self.failed(id, error); // 已经确认存在
+            current
+        }
+
+        // 记录一次停止尝试的结果，需要继续重试则返回已经失败的次数
+        fn stop_attempted_at(
+            &mut self,
+            id: u64,
+            result: Result<(), String>,
+            max_attempts: u32,
+            now: TimestampNanos,
+        ) -> Option<u32> {
+            let managed = self.canisters.get(&id)?;
+            if managed.state != CanisterLifecycleState::Stopping {
+                return None; // 已经被其他流程处理
+            }
+            let error = match result {
+                Ok(()) => match self.transit_at(id, CanisterLifecycleState::Stopped, now) {
+                    Ok(_) => return None,
+                    Err(error) => error,
+                },
+                Err(error) => error,
+            };
+            let managed = self.canisters.get_mut(&id)?;
+            managed.failures += 1;
+            managed.error = Some(error);
+            (managed.failures < max_attempts).then_some(managed.failures)
+        }
+
+        fn stuck_at(&self, timeout: DurationNanos, now: TimestampNanos) -> Vec<(u64, CanisterId)> {
+            self.canisters
+                .values()
+                .filter(|managed| managed.state == CanisterLifecycleState::Stopping)
+                .filter(|managed| {
+                    let elapsed = now.into_inner().saturating_sub(managed.since.into_inner());
+                    timeout.into_inner() <= elapsed.max(0) as u128
+                })
+                .filter_map(|managed| managed.canister_id.map(|canister_id| (managed.id, canister_id)))
+                .collect()
+        }
+    }
+
+    impl CanisterLifecyclable<ManagedCanister> for ManagedCanisters {
+        // 查询
+        fn lifecycle_find_all(&self) -> Vec<&ManagedCanister> {
+            self.canisters.values().collect()
+        }
+        fn lifecycle_find(&self, id: u64) -> Option<&ManagedCanister> {
+            self.canisters.get(&id)
+        }
+        fn lifecycle_find_by_canister(&self, canister_id: &CanisterId) -> Option<&ManagedCanister> {
+            self.canisters
+                .values()
+                .find(|managed| managed.canister_id.as_ref() == Some(canister_id))
+        }
+        fn lifecycle_interrupted(&self) -> Vec<(u64, Option<CanisterId>, CanisterLifecycleState)> {
+            self.canisters
+                .values()
+                .filter(|managed| managed.state.is_transitional())
+                .map(|managed| (managed.id, managed.canister_id, managed.state))
+                .collect()
+        }
+        fn lifecycle_stuck(&self, timeout: DurationNanos) -> Vec<(u64, CanisterId)> {
+            self.stuck_at(timeout, crate::times::now())
+        }
+
+        // 修改
+        fn lifecycle_create(&mut self) -> u64 {
+            self.create_at(crate::times::now())
+        }
+        fn lifecycle_created(&mut self, id: u64, canister_id: CanisterId) -> Result<(), String> {
+            self.created_at(id, canister_id, crate::times::now())
+        }
+        fn lifecycle_transit(&mut self, id: u64, state: CanisterLifecycleState) -> Result<CanisterId, String> {
+            self.transit_at(id, state, crate::times::now())
+        }
+        fn lifecycle_failed(&mut self, id: u64, error: String) -> Result<(), String> {
+            self.failed(id, error)
+        }
+        fn lifecycle_resumed(
+            &mut self,
+            id: u64,
+            result: Result<CanisterLifecycleState, String>,
+        ) -> CanisterLifecycleState {
+            self.resumed_at(id, result, crate::times::now())
+        }
+        fn lifecycle_remove(&mut self, id: u64) -> Option<ManagedCanister> {
+            self.canisters.remove(&id)
+        }
+    }
+
+    // 第 failures 次失败后的等待时间，每次翻倍
+    fn stop_retry_delay(backoff: std::time::Duration, failures: u32) -> std::time::Duration {
+        if failures == 0 {
+            return std::time::Duration::ZERO;
+        }
+        let factor = 1_u32.checked_shl(failures - 1).unwrap_or(u32::MAX);
+        backoff.saturating_mul(factor)
+    }
+
+    /// 用定时器停止处于停止中的受管罐子，失败后按退避时间重新注册定时器，直到停止或者失败 `max_attempts` 次
+    ///
+    /// 每次尝试都在新的定时器回调中执行，结果通过 `with_canisters` 记录，
+    /// 例如 `|f| with_mut_state(|s| f(&mut s.lifecycle))`。
+    /// 第一次尝试的等待时间按已经记录的失败次数计算。升级会清除定时器，需要在 post_upgrade 中重新调用
+    pub fn start_stop_managed_canister<A>(id: u64, max_attempts: u32, backoff: std::time::Duration, with_canisters: A)
+    where
+        A: Fn(&mut dyn FnMut(&mut ManagedCanisters)) + Clone + 'static,
+    {
+        let mut target = None;
+        with_canisters(&mut |canisters| {
+            target = canisters
+                .canisters
+                .get(&id)
+                .filter(|managed| managed.state == CanisterLifecycleState::Stopping)
+                .and_then(|managed| managed.canister_id.map(|canister_id| (canister_id, managed.failures)));
+        });
+        let Some((canister_id, failures)) = target else {
+            return;
+        };
+        ic_cdk_timers::set_timer(stop_retry_delay(backoff, failures), async move {
+            let result = super::stop_canister_once(canister_id)
+                .await
+                .map_err(|err| err.to_string());
+            let mut result = Some(result);
+            let mut retry = None;
+            with_canisters(&mut |canisters| {
+                if let Some(result) = result.take() {
+                    retry = canisters.stop_attempted_at(id, result, max_attempts, crate::times::now());
+                }
+            });
+            if retry.is_some() {
+                start_stop_managed_canister(id, max_attempts, backoff, with_canisters);
+            }
+        });
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use std::time::Duration;
+
+        use candid::Principal;
+
+        use super::{CanisterLifecycleState::*, ManagedCanisters, stop_retry_delay};
+        use crate::types::{DurationNanos, TimestampNanos};
+
+        #[test]
+        fn validates_transitions_and_tracks_stuck_stops() {
+            let t = TimestampNanos::from;
+            let canister_id = Principal::from_slice(&[1]);
+            let mut canisters = ManagedCanisters::default();
+
+            let id = canisters.create_at(t(0));
+            assert!(canisters.transit_at(id, Running, t(0)).is_err()); // 还没有罐子 id
+            canisters.created_at(id, canister_id, t(1)).unwrap();
+            assert!(canisters.transit_at(id, Deleting, t(1)).is_err());
+            assert_eq!(canisters.transit_at(id, Running, t(2)), Ok(canister_id));
+
+            canisters.transit_at(id, Stopping, t(10)).unwrap();
+            canisters.failed(id, "timeout".to_string()).unwrap();
+            assert_eq!(canisters.canisters[&id].failures, 1);
+            assert!(canisters.stuck_at(DurationNanos::from(20), t(20)).is_empty());
+            assert_eq!(
+                canisters.stuck_at(DurationNanos::from(20), t(30)),
+                vec![(id, canister_id)]
+            );
+
+            // 升级后继续
+            canisters.resumed_at(id, Err("still stopping".to_string()), t(31));
+            assert_eq!(canisters.canisters[&id].failures, 2);
+            assert_eq!(canisters.resumed_at(id, Ok(Stopped), t(32)), Stopped);
+            assert_eq!(canisters.canisters[&id].error, None);
+
+            // 没有取回 cycles 不能删除，继续时的非法变更会记录为失败
+            assert!(canisters.transit_at(id, Deleting, t(33)).is_err());
+            assert_eq!(canisters.resumed_at(id, Ok(Deleting), t(33)), Stopped);
+            assert_eq!(canisters.canisters[&id].failures, 1);
+
+            canisters.transit_at(id, Running, t(34)).unwrap();
+            canisters.transit_at(id, Withdrawing, t(35)).unwrap();
+            assert_eq!(canisters.resumed_at(id, Ok(Stopping), t(36)), Stopping);
+            canisters.transit_at(id, Stopped, t(37)).unwrap();
+            canisters.transit_at(id, Deleting, t(40)).unwrap();
+            canisters.resumed_at(id, Ok(Deleted), t(41));
+            assert_eq!(canisters.canisters[&id].state, Deleted);
+            assert!(canisters.transit_at(id, Running, t(42)).is_err());
+
+            // 每次停止尝试单独记录，达到次数后不再重试
+            let id = canisters.create_at(t(60));
+            canisters.created_at(id, canister_id, t(60)).unwrap();
+            canisters.transit_at(id, Running, t(60)).unwrap();
+            canisters.transit_at(id, Stopping, t(61)).unwrap();
+            assert_eq!(canisters.stop_attempted_at(id, Err("busy".into()), 3, t(62)), Some(1));
+            assert_eq!(canisters.stop_attempted_at(id, Err("busy".into()), 3, t(63)), Some(2));
+            assert_eq!(canisters.stop_attempted_at(id, Err("busy".into()), 3, t(64)), None);
+            assert_eq!(canisters.canisters[&id].failures, 3);
+            assert_eq!(canisters.stop_attempted_at(id, Ok(()), 3, t(65)), None);
+            assert_eq!(canisters.canisters[&id].state, Stopped);
+            assert_eq!(canisters.stop_attempted_at(id, Err("late".into()), 3, t(66)), None);
+            assert_eq!(canisters.canisters[&id].failures, 0);
+            assert_eq!(stop_retry_delay(Duration::from_secs(1), 0), Duration::ZERO);
+            assert_eq!(stop_retry_delay(Duration::from_secs(1), 3), Duration::from_secs(4));
+
+            let bytes = crate::functions::stable::to_bytes(&canisters).unwrap();
+            let mut restored: ManagedCanisters = crate::functions::stable::from_bytes(&bytes).unwrap();
+            assert_eq!(restored.create_at(t(70)), 2);
+        }
+    }
+}
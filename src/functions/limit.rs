@@ -0,0 +1,309 @@
+//! 调用频率限制
+
+use crate::identity::CallerId;
+
+// ================== 功能 ==================
+
+/// 调用频率限制
+pub trait RateLimitable<State, Error> {
+    // 查询
+
+    /// 查询某调用者各方法的限流状态，不指定调用者则返回所有状态
+    fn rate_limit_query(&self, caller: Option<&CallerId>) -> Vec<State>;
+
+    // 修改
+
+    /// 消耗一次调用额度，额度不足则返回错误
+    fn rate_limit_consume(&mut self, caller: &CallerId, method: &str) -> Result<(), Error>;
+    /// 清理长时间未使用的限流状态，返回清理的数量
+    fn rate_limit_evict(&mut self) -> u64;
+}
+
+// ================== 简单实现 ==================
+
+/// 调用频率限制简单实现
+pub mod basic {
+    use std::collections::HashMap;
+
+    use candid::CandidType;
+    use serde::{Deserialize, Serialize};
+
+    use crate::{
+        functions::types::RateLimitable,
+        identity::CallerId,
+        types::{DurationNanos, TimestampNanos},
+    };
+
+    /// 令牌桶规则
+    #[derive(CandidType, Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct RateLimitRule {
+        /// 桶容量，即允许的最大突发调用次数
+        pub capacity: u64,
+        /// 每恢复一次调用额度需要的时间
+        pub refill_interval: DurationNanos,
+    }
+
+    /// 匿名用户的限流策略
+    #[derive(CandidType, Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+    pub enum AnonymousRateLimit {
+        /// 拒绝匿名用户调用
+        #[default]
+        Reject,
+        /// 与其他用户使用相同的规则
+        Same,
+        /// 所有匿名调用共用一个单独的规则
+        Rule(RateLimitRule),
+    }
+
+    /// 限流配置
+    #[derive(CandidType, Serialize, Deserialize, Debug, Clone, Default)]
+    pub struct RateLimitConfig {
+        /// 默认规则，没有则不限制未单独配置的方法
+        pub default_rule: Option<RateLimitRule>,
+        /// 按方法名单独配置的规则
+        pub method_rules: HashMap<String, RateLimitRule>,
+        /// 匿名用户的策略
+        pub anonymous: AnonymousRateLimit,
+        /// 状态闲置超过该时间则可以被清理，没有则使用规则恢复满额度所需的时间
+        pub idle_timeout: Option<DurationNanos>,
+    }
+
+    impl RateLimitConfig {
+        fn rule(&self, caller: &CallerId, method: &str) -> Result<Option<RateLimitRule>, RateLimitError> {
+            if *caller == CallerId::anonymous() {
+                match self.anonymous {
+                    AnonymousRateLimit::Reject => return Err(RateLimitError::AnonymousRejected),
+                    AnonymousRateLimit::Same => {}
+                    AnonymousRateLimit::Rule(rule) => return Ok(Some(rule)),
+                }
+            }
+            Ok(self.method_rules.get(method).copied().or(self.default_rule))
+        }
+    }
+
+    /// 限流错误
+    #[derive(CandidType, Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+    pub enum RateLimitError {
+        /// 不允许匿名用户调用
+        AnonymousRejected,
+        /// 调用过于频繁
+        TooManyRequests {
+            /// 至少等待该时间后才能再次调用
+            retry_after: DurationNanos,
+        },
+    }
+    impl std::fmt::Display for RateLimitError {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            match self {
+                RateLimitError::AnonymousRejected => write!(f, "Anonymous caller is not allowed."),
+                RateLimitError::TooManyRequests { retry_after } => {
+                    write!(f, "Too many requests. Retry after {retry_after} nanoseconds.")
+                }
+            }
+        }
+    }
+    impl std::error::Error for RateLimitError {}
+
+    /// 单个令牌桶
+    #[derive(CandidType, Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+    pub struct RateLimitBucket {
+        /// 剩余调用额度
+        pub tokens: u64,
+        /// 额度最后一次结算的时间
+        pub updated_at: TimestampNanos,
+        /// 最后一次调用的时间
+        pub last_called_at: TimestampNanos,
+    }
+
+    impl RateLimitBucket {
+        // 按经过的时间恢复额度
+        fn refill(&mut self, rule: &RateLimitRule, now: TimestampNanos) {
+            let interval = rule.refill_interval.into_inner();
+            let elapsed = u128::try_from(now.into_inner() - self.updated_at.into_inner()).unwrap_or_default();
+            if interval == 0 {
+                self.tokens = rule.capacity;
+                self.updated_at = now;
+                return;
+            }
+            let refilled = elapsed / interval;
+            self.tokens = u64::try_from(u128::from(self.tokens).saturating_add(refilled))
+                .unwrap_or(u64::MAX)
+                .min(rule.capacity);
+            if self.tokens == rule.capacity {
+                self.updated_at = now;
+            } else {
+                self.updated_at = (self.updated_at.into_inner() + (refilled * interval) as i128).into();
+            }
+        }
+
+        // 恢复一次额度还需的时间
+        fn retry_after(&self, rule: &RateLimitRule, now: TimestampNanos) -> DurationNanos {
+            let elapsed = u128::try_from(now.into_inner() - self.updated_at.into_inner()).unwrap_or_default();
+            rule.refill_interval.into_inner().saturating_sub(elapsed).into()
+        }
+    }
+
+    /// 单个限流状态
+    #[derive(CandidType, Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+    pub struct RateLimitState {
+        /// 调用者
+        pub caller: CallerId,
+        /// 方法名
+        pub method: String,
+        /// 令牌桶
+        pub bucket: RateLimitBucket,
+    }
+
+    /// 持久化的限流对象
+    #[derive(CandidType, Serialize, Deserialize, Debug, Clone, Default)]
+    pub struct RateLimiter {
+        /// 限流配置
+        pub config: RateLimitConfig,
+        /// 按调用者和方法名记录的令牌桶
+        pub buckets: HashMap<(CallerId, String), RateLimitBucket>,
+    }
+
+    impl RateLimiter {
+        /// 使用配置构造
+        pub fn new(config: RateLimitConfig) -> Self {
+            Self {
+                config,
+                buckets: Default::default(),
+            }
+        }
+
+        fn consume_at(&mut self, caller: &CallerId, method: &str, now: TimestampNanos) -> Result<(), RateLimitError> {
+            let Some(rule) = self.config.rule(caller, method)? else {
+                return Ok(());
+            };
+            let bucket = self
+                .buckets
+                .entry((*caller, method.to_string()))
+                .or_insert_with(|| RateLimitBucket {
+                    tokens: rule.capacity,
+                    updated_at: now,
+                    last_called_at: now,
+                });
+            bucket.refill(&rule, now);
+            if bucket.tokens == 0 {
+                return Err(RateLimitError::TooManyRequests {
+                    retry_after: bucket.retry_after(&rule, now),
+                });
+            }
+            bucket.tokens -= 1;
+            bucket.last_called_at = now;
+            Ok(())
+        }
+
+        fn evict_at(&mut self, now: TimestampNanos) -> u64 {
+            let before = self.buckets.len();
+            let config = &self.config;
+            self.buckets.retain(|(caller, method), bucket| {
+                let idle_timeout = match config.idle_timeout {
+                    Some(idle_timeout) => idle_timeout.into_inner(),
+                    None => match config.rule(caller, method) {
+                        Ok(Some(rule)) => rule
+                            .refill_interval
+                            .into_inner()
+                            .saturating_mul(u128::from(rule.capacity)),
+                        _ => 0, // 已经不再限制的状态直接清理
+                    },
+                };
+                let idle = u128::try_from(now.into_inner() - bucket.last_called_at.into_inner()).unwrap_or_default();
+                idle < idle_timeout
+            });
+            (before - self.buckets.len()) as u64
+        }
+    }
+
+    impl RateLimitable<RateLimitState, RateLimitError> for RateLimiter {
+        // 查询
+        fn rate_limit_query(&self, caller: Option<&CallerId>) -> Vec<RateLimitState> {
+            let mut states: Vec<RateLimitState> = self
+                .buckets
+                .iter()
+                .filter(|((c, _), _)| caller.is_none_or(|caller| c == caller))
+                .map(|((caller, method), bucket)| RateLimitState {
+                    caller: *caller,
+                    method: method.clone(),
+                    bucket: bucket.clone(),
+                })
+                .collect();
+            states.sort_by(|a, b| (a.caller, &a.method).cmp(&(b.caller, &b.method)));
+            states
+        }
+
+        // 修改
+        fn rate_limit_consume(&mut self, caller: &CallerId, method: &str) -> Result<(), RateLimitError> {
+            self.consume_at(caller, method, crate::times::now())
+        }
+        fn rate_limit_evict(&mut self) -> u64 {
+            self.evict_at(crate::times::now())
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use candid::Principal;
+
+        use super::{AnonymousRateLimit, RateLimitConfig, RateLimitError, RateLimitRule, RateLimiter};
+        use crate::types::TimestampNanos;
+
+        fn limiter() -> RateLimiter {
+            RateLimiter::new(RateLimitConfig {
+                default_rule: Some(RateLimitRule {
+                    capacity: 2,
+                    refill_interval: 10_u128.into(),
+                }),
+                ..Default::default()
+            })
+        }
+
+        #[test]
+        fn bucket_limits_bursts_and_refills_over_time() {
+            let caller = Principal::management_canister();
+            let mut limiter = limiter();
+
+            assert!(limiter.consume_at(&caller, "a", TimestampNanos::from(0)).is_ok());
+            assert!(limiter.consume_at(&caller, "a", TimestampNanos::from(1)).is_ok());
+            assert_eq!(
+                limiter.consume_at(&caller, "a", TimestampNanos::from(4)),
+                Err(RateLimitError::TooManyRequests {
+                    retry_after: 6_u128.into()
+                })
+            );
+            assert!(limiter.consume_at(&caller, "b", TimestampNanos::from(4)).is_ok());
+            assert!(limiter.consume_at(&caller, "a", TimestampNanos::from(10)).is_ok());
+            assert!(limiter.consume_at(&caller, "a", TimestampNanos::from(11)).is_err());
+        }
+
+        #[test]
+        fn anonymous_callers_follow_their_own_policy() {
+            let anonymous = Principal::anonymous();
+            let mut limiter = limiter();
+            assert_eq!(
+                limiter.consume_at(&anonymous, "a", TimestampNanos::from(0)),
+                Err(RateLimitError::AnonymousRejected)
+            );
+
+            limiter.config.anonymous = AnonymousRateLimit::Rule(RateLimitRule {
+                capacity: 1,
+                refill_interval: 10_u128.into(),
+            });
+            assert!(limiter.consume_at(&anonymous, "a", TimestampNanos::from(0)).is_ok());
+            assert!(limiter.consume_at(&anonymous, "a", TimestampNanos::from(1)).is_err());
+        }
+
+        #[test]
+        fn evicts_idle_buckets() {
+            let caller = Principal::management_canister();
+            let mut limiter = limiter();
+            limiter.consume_at(&caller, "a", TimestampNanos::from(0)).unwrap();
+            limiter.consume_at(&caller, "b", TimestampNanos::from(15)).unwrap();
+
+            assert_eq!(limiter.evict_at(TimestampNanos::from(20)), 1);
+            assert_eq!(limiter.buckets.len(), 1);
+            assert!(limiter.buckets.contains_key(&(caller, "b".to_string())));
+        }
+    }
+}
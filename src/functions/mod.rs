@@ -10,9 +10,36 @@ pub mod pausable;
 /// 定时任务功能
 pub mod schedule;
 
+/// 定时表达式
+pub mod cron;
+
+/// 失败重试的任务队列
+pub mod queue;
+
+/// 持久化的延时任务
+pub mod delay;
+
+/// 子罐子集群管理
+pub mod fleet;
+
+/// Wasm 模块仓库
+pub mod wasm;
+
+/// 受管罐子的生命周期
+pub mod lifecycle;
+
+/// 自动充值 cycles
+pub mod topup;
+
+/// cycles 消耗趋势
+pub mod burn;
+
 /// 权限功能
 pub mod permission;
 
+/// 调用频率限制功能
+pub mod limit;
+
 /// 记录功能
 pub mod record;
 
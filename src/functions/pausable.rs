@@ -1,11 +1,21 @@
 //! 维护状态
 
+use crate::identity::CallerId;
+
 // ================== 功能 ==================
 
 /// 维护原因
 pub trait Reasonable {
     /// 维护原因
     fn message(&self) -> &str;
+    /// 是否维护某方法分组，默认维护所有分组
+    fn covers(&self, _group: &str) -> bool {
+        true
+    }
+    /// 是否维护所有分组，只维护部分分组的不影响整个罐子，默认维护所有分组
+    fn covers_all(&self) -> bool {
+        true
+    }
 }
 
 /// 维护记录
@@ -22,17 +32,37 @@ pub trait Pausable<Reason: Reasonable> {
 
     // 默认方法
 
-    /// 是否维护中
+    /// 修改维护状态并记录操作人，默认不记录
+    fn pause_replace_by(&mut self, _caller: CallerId, reason: Option<Reason>) {
+        self.pause_replace(reason)
+    }
+
+    /// 当前实际生效的维护状态，默认只有手动设置的维护状态
+    fn pause_effective(&self) -> Option<&Reason> {
+        self.pause_query().as_ref()
+    }
+    /// 是否维护中，只维护部分分组时不算，分组请使用 [`Pausable::pause_must_be_running_for`]
     fn pause_is_paused(&self) -> bool {
-        self.pause_query().is_some()
+        self.pause_effective().is_some_and(Reasonable::covers_all)
     }
     /// 是否正常运行
     fn pause_is_running(&self) -> bool {
         !self.pause_is_paused()
     }
-    /// 正常运行中才能继续
+    /// 正常运行中才能继续，只维护部分分组时不受影响
     fn pause_must_be_running(&self) -> Result<(), String> {
-        if let Some(reason) = &self.pause_query() {
+        if let Some(reason) = self.pause_effective()
+            && reason.covers_all()
+        {
+            return Err(format!("Canister is paused: {}", reason.message()));
+        }
+        Ok(())
+    }
+    /// 某方法分组正常运行中才能继续，未被维护的分组不受影响
+    fn pause_must_be_running_for(&self, group: &str) -> Result<(), String> {
+        if let Some(reason) = self.pause_effective()
+            && reason.covers(group)
+        {
             return Err(format!("Canister is paused: {}", reason.message()));
         }
         Ok(())
@@ -50,17 +80,32 @@ pub trait Pausable<Reason: Reasonable> {
 
 /// 维护功能简单实现
 pub mod basic {
-    use std::fmt::Display;
+    use std::{collections::HashSet, fmt::Display};
 
     use candid::CandidType;
     use serde::{Deserialize, Serialize};
 
     use crate::{
         functions::types::{Pausable, Reasonable},
-        types::TimestampNanos,
+        identity::CallerId,
+        types::{PageData, QueryPage, QueryPageError, TimestampNanos},
     };
 
+    /// 维护类型
+    #[derive(CandidType, Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+    pub enum PauseCategory {
+        /// 日常维护
+        #[default]
+        Maintenance,
+        /// 故障处理
+        Incident,
+        /// 版本升级
+        Upgrade,
+    }
+
     /// 维护原因对象
+    ///
+    /// ! 2.0 起增加了字段，使用 [`PauseReason::new`] 等构造方法构造
     #[derive(CandidType, Serialize, Deserialize, Debug, Clone)]
     pub struct PauseReason {
         /// 进入维护状态的时间
@@ -69,6 +114,14 @@ pub mod basic {
 
         /// 维护原因
         pub message: String,
+
+        /// 被维护的方法分组，没有则维护所有分组
+        #[serde(default)]
+        pub groups: Option<HashSet<String>>,
+
+        /// 维护类型
+        #[serde(default)]
+        pub category: PauseCategory,
     }
 
     impl Display for PauseReason {
@@ -83,31 +136,410 @@ pub mod basic {
         fn message(&self) -> &str {
             &self.message
         }
+        fn covers(&self, group: &str) -> bool {
+            self.groups.as_ref().is_none_or(|groups| groups.contains(group))
+        }
+        fn covers_all(&self) -> bool {
+            self.groups.is_none()
+        }
     }
 
     impl PauseReason {
         /// 构造维护原因
         pub fn new(message: String) -> Self {
+            Self::new_at(message, crate::times::now())
+        }
+        /// 构造指定时间的维护原因
+        pub fn new_at(message: String, paused_at: TimestampNanos) -> Self {
             PauseReason {
-                paused_at: crate::times::now(),
+                paused_at,
                 message,
+                groups: None,
+                category: Default::default(),
             }
         }
+        /// 只维护指定的方法分组
+        pub fn with_groups(mut self, groups: HashSet<String>) -> Self {
+            self.groups = Some(groups);
+            self
+        }
+        /// 指定维护类型
+        pub fn with_category(mut self, category: PauseCategory) -> Self {
+            self.category = category;
+            self
+        }
+    }
+
+    /// 维护状态变化
+    #[derive(CandidType, Serialize, Deserialize, Debug, Clone)]
+    pub enum PauseAction {
+        /// 进入维护状态或修改维护原因
+        Paused(PauseReason),
+        /// 恢复运行，保留之前的维护原因
+        Resumed(PauseReason),
+    }
+
+    /// 维护状态变化记录
+    #[derive(CandidType, Serialize, Deserialize, Debug, Clone)]
+    pub struct PauseEvent {
+        /// 发生时间
+        pub created: TimestampNanos,
+        /// 操作人
+        pub caller: CallerId,
+        /// 状态变化
+        pub action: PauseAction,
+    }
+
+    /// 计划中的维护窗口
+    #[derive(CandidType, Serialize, Deserialize, Debug, Clone)]
+    pub struct PauseWindow {
+        /// 开始时间 包含
+        pub start: TimestampNanos,
+        /// 结束时间 不包含
+        pub end: TimestampNanos,
+        /// 维护原因
+        pub reason: PauseReason,
+    }
+
+    impl PauseWindow {
+        /// 某时间是否在维护窗口内
+        pub fn contains(&self, now: TimestampNanos) -> bool {
+            self.start <= now && now < self.end
+        }
+    }
+
+    /// cycles 不足时自动维护
+    #[derive(CandidType, Serialize, Deserialize, Debug, Clone)]
+    pub struct PauseCyclesThreshold {
+        /// cycles 低于该值则自动进入维护状态
+        pub threshold: u128,
+        /// 自动维护时使用的维护原因
+        pub message: String,
     }
 
     /// 记录维护状态
-    #[derive(CandidType, Serialize, Deserialize, Debug, Clone, Default)]
-    pub struct Pause(Option<PauseReason>);
+    ///
+    /// ! 2.0 起不再是元组结构体，使用 [`Pause::new`] 构造，使用 [`Pause::reason`] 读取
+    #[derive(CandidType, Serialize, Debug, Clone)]
+    pub struct Pause {
+        /// 手动设置的维护状态
+        pub reason: Option<PauseReason>,
+        /// 计划中的维护窗口
+        pub windows: Vec<PauseWindow>,
+        /// cycles 不足时自动维护
+        pub cycles_threshold: Option<PauseCyclesThreshold>,
+        /// 最多保留的维护状态变化记录条数
+        pub history_limit: u64,
+        /// 维护状态变化记录 正序
+        pub history: Vec<PauseEvent>,
+        /// 维护窗口的开始和结束已经记录到该时间 包含
+        pub windows_recorded_at: TimestampNanos,
+    }
+
+    const DEFAULT_PAUSE_HISTORY_LIMIT: u64 = 1024;
+
+    fn default_history_limit() -> u64 {
+        DEFAULT_PAUSE_HISTORY_LIMIT
+    }
+
+    fn default_windows_recorded_at() -> TimestampNanos {
+        TimestampNanos::from(0)
+    }
+
+    impl Default for Pause {
+        fn default() -> Self {
+            Self {
+                reason: Default::default(),
+                windows: Default::default(),
+                cycles_threshold: Default::default(),
+                history_limit: DEFAULT_PAUSE_HISTORY_LIMIT,
+                history: Default::default(),
+                windows_recorded_at: default_windows_recorded_at(),
+            }
+        }
+    }
+
+    // 兼容旧版本仅保存维护原因的数据，旧数据为空或直接是维护原因的字段
+    #[derive(Deserialize)]
+    struct PauseRepr {
+        #[serde(alias = "timestamp_nanos")]
+        paused_at: Option<TimestampNanos>,
+        message: Option<String>,
+        reason: Option<PauseReason>,
+        #[serde(default)]
+        windows: Vec<PauseWindow>,
+        #[serde(default)]
+        cycles_threshold: Option<PauseCyclesThreshold>,
+        #[serde(default = "default_history_limit")]
+        history_limit: u64,
+        #[serde(default)]
+        history: Vec<PauseEvent>,
+        #[serde(default = "default_windows_recorded_at")]
+        windows_recorded_at: TimestampNanos,
+    }
+
+    impl From<PauseRepr> for Pause {
+        fn from(value: PauseRepr) -> Self {
+            let reason = match (value.paused_at, value.message) {
+                (Some(paused_at), Some(message)) => Some(PauseReason::new_at(message, paused_at)),
+                _ => value.reason,
+            };
+            Pause {
+                reason,
+                windows: value.windows,
+                cycles_threshold: value.cycles_threshold,
+                history_limit: value.history_limit,
+                history: value.history,
+                windows_recorded_at: value.windows_recorded_at,
+            }
+        }
+    }
+
+    impl<'de> Deserialize<'de> for Pause {
+        fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            struct PauseVisitor;
+
+            impl<'de> serde::de::Visitor<'de> for PauseVisitor {
+                type Value = Pause;
+
+                fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                    f.write_str("pause state or legacy pause reason")
+                }
+                fn visit_none<E: serde::de::Error>(self) -> Result<Self::Value, E> {
+                    Ok(Pause::default())
+                }
+                fn visit_unit<E: serde::de::Error>(self) -> Result<Self::Value, E> {
+                    Ok(Pause::default())
+                }
+                fn visit_some<D: serde::Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+                    deserializer.deserialize_any(self)
+                }
+                fn visit_map<A: serde::de::MapAccess<'de>>(self, map: A) -> Result<Self::Value, A::Error> {
+                    PauseRepr::deserialize(serde::de::value::MapAccessDeserializer::new(map)).map(Into::into)
+                }
+            }
+
+            deserializer.deserialize_any(PauseVisitor)
+        }
+    }
+
+    impl Pause {
+        /// 构造维护状态，其他设置使用默认值
+        pub fn new(reason: Option<PauseReason>) -> Self {
+            Self {
+                reason,
+                ..Default::default()
+            }
+        }
+
+        /// 手动设置的维护状态
+        pub fn reason(&self) -> Option<&PauseReason> {
+            self.reason.as_ref()
+        }
+
+        /// 计划中的维护窗口
+        pub fn windows(&self) -> &[PauseWindow] {
+            &self.windows
+        }
+
+        /// cycles 不足时自动维护的设置
+        pub fn cycles_threshold(&self) -> Option<&PauseCyclesThreshold> {
+            self.cycles_threshold.as_ref()
+        }
+
+        /// 指定时间生效的维护状态，手动设置的维护状态优先
+        pub fn effective_at(&self, now: TimestampNanos) -> Option<&PauseReason> {
+            self.reason.as_ref().or_else(|| {
+                self.windows
+                    .iter()
+                    .find(|window| window.contains(now))
+                    .map(|w| &w.reason)
+            })
+        }
+
+        /// 把维护窗口的开始和结束记录到变化记录中，记录时间为窗口的开始和结束时间
+        ///
+        /// 窗口是按时间自动生效的，需要定时调用 (例如在定时器中) 才能及时记录；计划和取消窗口时会自动调用
+        pub fn pause_record_windows(&mut self) {
+            self.record_windows_at(crate::times::now());
+        }
+
+        fn record_windows_at(&mut self, now: TimestampNanos) {
+            let recorded_at = self.windows_recorded_at;
+            if now <= recorded_at {
+                return;
+            }
+            let mut events: Vec<PauseEvent> = Vec::new();
+            for window in &self.windows {
+                let action = |time: TimestampNanos, action: PauseAction| {
+                    (recorded_at < time && time <= now).then(|| PauseEvent {
+                        created: time,
+                        caller: CallerId::anonymous(),
+                        action,
+                    })
+                };
+                events.extend(action(window.start, PauseAction::Paused(window.reason.clone())));
+                events.extend(action(window.end, PauseAction::Resumed(window.reason.clone())));
+            }
+            events.sort_by_key(|event| event.created);
+            for event in events {
+                self.push_event(event);
+            }
+            self.windows_recorded_at = now;
+        }
+
+        /// 计划维护窗口，同时清理已经结束的窗口
+        pub fn pause_schedule(
+            &mut self,
+            start: TimestampNanos,
+            end: TimestampNanos,
+            message: String,
+            groups: Option<HashSet<String>>,
+        ) -> Result<(), String> {
+            self.schedule_at(start, end, message, groups, crate::times::now())
+        }
+
+        fn schedule_at(
+            &mut self,
+            start: TimestampNanos,
+            end: TimestampNanos,
+            message: String,
+            groups: Option<HashSet<String>>,
+            now: TimestampNanos,
+        ) -> Result<(), String> {
+            if end <= start {
+                return Err(format!("Pause window end({end}) must be after start({start})."));
+            }
+            if end <= now {
+                return Err(format!("Pause window end({end}) is already passed."));
+            }
+            self.record_windows_at(now);
+            self.windows.retain(|window| now < window.end);
+            let mut reason = PauseReason::new_at(message, start);
+            reason.groups = groups;
+            let window = PauseWindow { start, end, reason };
+            if window.contains(now) {
+                // 开始时间已过，立即记录
+                self.push_event(PauseEvent {
+                    created: now,
+                    caller: CallerId::anonymous(),
+                    action: PauseAction::Paused(window.reason.clone()),
+                });
+            }
+            self.windows.push(window);
+            self.windows.sort_by_key(|window| window.start);
+            Ok(())
+        }
+
+        /// 取消开始时间为指定值的维护窗口，返回被取消的窗口
+        pub fn pause_unschedule(&mut self, start: TimestampNanos) -> Vec<PauseWindow> {
+            self.unschedule_at(start, crate::times::now())
+        }
+
+        fn unschedule_at(&mut self, start: TimestampNanos, now: TimestampNanos) -> Vec<PauseWindow> {
+            self.record_windows_at(now);
+            let (removed, windows): (Vec<PauseWindow>, _) = std::mem::take(&mut self.windows)
+                .into_iter()
+                .partition(|window| window.start == start);
+            self.windows = windows;
+            for window in removed.iter().filter(|window| window.contains(now)) {
+                // 生效中的窗口提前结束
+                self.push_event(PauseEvent {
+                    created: now,
+                    caller: CallerId::anonymous(),
+                    action: PauseAction::Resumed(window.reason.clone()),
+                });
+            }
+            removed
+        }
+
+        /// 设置 cycles 不足时自动维护
+        pub fn pause_set_cycles_threshold(&mut self, cycles_threshold: Option<PauseCyclesThreshold>) {
+            self.cycles_threshold = cycles_threshold;
+        }
+
+        /// 检查当前 cycles 余额，低于阈值且未在维护中则进入维护状态
+        ///
+        /// 返回是否因此进入了维护状态，恢复运行需要手动处理
+        pub fn pause_check_cycles(&mut self) -> bool {
+            self.check_cycles_at(
+                crate::canister::self_canister_cycles(),
+                crate::identity::self_canister_id(),
+                crate::times::now(),
+            )
+        }
+
+        fn check_cycles_at(&mut self, cycles: u128, caller: CallerId, now: TimestampNanos) -> bool {
+            let Some(cycles_threshold) = &self.cycles_threshold else {
+                return false;
+            };
+            if self.reason.is_some() || cycles_threshold.threshold <= cycles {
+                return false;
+            }
+            let reason =
+                PauseReason::new_at(cycles_threshold.message.clone(), now).with_category(PauseCategory::Incident);
+            self.replace_at(caller, Some(reason), now);
+            true
+        }
+
+        fn replace_at(&mut self, caller: CallerId, reason: Option<PauseReason>, now: TimestampNanos) {
+            let action = match (&reason, self.reason.take()) {
+                (Some(reason), _) => Some(PauseAction::Paused(reason.clone())),
+                (None, Some(previous)) => Some(PauseAction::Resumed(previous)),
+                (None, None) => None,
+            };
+            self.reason = reason;
+            if let Some(action) = action {
+                self.push_event(PauseEvent {
+                    created: now,
+                    caller,
+                    action,
+                });
+            }
+        }
+
+        fn push_event(&mut self, event: PauseEvent) {
+            let history_limit = usize::try_from(self.history_limit).unwrap_or(usize::MAX);
+            if history_limit == 0 {
+                self.history.clear();
+                return;
+            }
+            if history_limit <= self.history.len() {
+                self.history.drain(..self.history.len() - history_limit + 1);
+            }
+            self.history.push(event);
+        }
+
+        /// 分页查询维护状态变化记录，最新的在前
+        pub fn pause_history_by_page(
+            &self,
+            page: &QueryPage,
+            max_page_size: u32,
+        ) -> Result<PageData<&PauseEvent>, QueryPageError> {
+            page.query_desc_by_list(&self.history, max_page_size)
+        }
+    }
 
     impl Pausable<PauseReason> for Pause {
         // 查询
         fn pause_query(&self) -> &Option<PauseReason> {
-            &self.0
+            &self.reason
         }
         // 修改
-        // 设置维护状态
+        // 设置维护状态，不知道操作人，记录为匿名身份
         fn pause_replace(&mut self, reason: Option<PauseReason>) {
-            self.0 = reason;
+            self.replace_at(CallerId::anonymous(), reason, crate::times::now());
+        }
+        // 设置维护状态并记录
+        fn pause_replace_by(&mut self, caller: CallerId, reason: Option<PauseReason>) {
+            self.replace_at(caller, reason, crate::times::now());
+        }
+        // 包括计划中的维护窗口
+        fn pause_effective(&self) -> Option<&PauseReason> {
+            if self.reason.is_some() || self.windows.is_empty() {
+                return self.reason.as_ref();
+            }
+            self.effective_at(crate::times::now())
         }
     }
 
@@ -116,8 +548,15 @@ pub mod basic {
         use ciborium::value::Value;
         use serde::Serialize;
 
-        use super::PauseReason;
-        use crate::types::TimestampNanos;
+        use std::collections::HashSet;
+
+        use candid::Principal;
+
+        use super::{Pause, PauseAction, PauseCategory, PauseCyclesThreshold, PauseEvent, PauseReason};
+        use crate::{
+            functions::types::{Pausable, Reasonable},
+            types::{QueryPage, TimestampNanos},
+        };
 
         #[derive(Serialize)]
         struct LegacyPauseReason {
@@ -152,5 +591,187 @@ pub mod basic {
             assert!(keys.contains(&"paused_at"));
             assert!(!keys.contains(&"timestamp_nanos"));
         }
+
+        fn legacy_none_cbor() -> Vec<u8> {
+            let mut cbor = Vec::new();
+            ciborium::ser::into_writer(&Option::<PauseReason>::None, &mut cbor).unwrap();
+            cbor
+        }
+
+        #[test]
+        fn deserializes_legacy_pause_and_round_trips_current_pause() {
+            let legacy = Some(PauseReason::new_at("legacy".to_string(), TimestampNanos::from(1)));
+            let mut legacy_cbor = Vec::new();
+            ciborium::ser::into_writer(&legacy, &mut legacy_cbor).unwrap();
+            let decoded: Pause = ciborium::de::from_reader(legacy_cbor.as_slice()).unwrap();
+            assert_eq!(decoded.reason.unwrap().message, "legacy");
+
+            let mut pause = Pause::default();
+            pause
+                .schedule_at(
+                    TimestampNanos::from(10),
+                    TimestampNanos::from(20),
+                    "window".to_string(),
+                    None,
+                    TimestampNanos::from(0),
+                )
+                .unwrap();
+            let mut current_cbor = Vec::new();
+            ciborium::ser::into_writer(&pause, &mut current_cbor).unwrap();
+            let decoded: Pause = ciborium::de::from_reader(current_cbor.as_slice()).unwrap();
+            assert!(decoded.reason.is_none());
+            assert_eq!(decoded.windows.len(), 1);
+
+            pause.replace_at(
+                Principal::anonymous(),
+                Some(PauseReason::new_at("manual".to_string(), TimestampNanos::from(2))),
+                TimestampNanos::from(2),
+            );
+            let decoded: Pause = candid::decode_one(&candid::encode_one(&pause).unwrap()).unwrap();
+            assert_eq!(decoded.reason.unwrap().message, "manual");
+            assert_eq!(decoded.windows.len(), 1);
+
+            let empty: Pause = ciborium::de::from_reader(legacy_none_cbor().as_slice()).unwrap();
+            assert!(empty.reason.is_none());
+        }
+
+        #[test]
+        fn scheduled_window_pauses_only_listed_groups_while_active() {
+            let mut pause = Pause::default();
+            let groups = HashSet::from(["transfer".to_string()]);
+            pause
+                .schedule_at(
+                    TimestampNanos::from(10),
+                    TimestampNanos::from(20),
+                    "upgrade".to_string(),
+                    Some(groups),
+                    TimestampNanos::from(0),
+                )
+                .unwrap();
+            assert!(
+                pause
+                    .schedule_at(
+                        TimestampNanos::from(5),
+                        TimestampNanos::from(5),
+                        "empty".to_string(),
+                        None,
+                        TimestampNanos::from(0)
+                    )
+                    .is_err()
+            );
+
+            assert!(pause.effective_at(TimestampNanos::from(9)).is_none());
+            let reason = pause.effective_at(TimestampNanos::from(10)).unwrap();
+            assert!(reason.covers("transfer"));
+            assert!(!reason.covers("query"));
+            assert!(pause.effective_at(TimestampNanos::from(20)).is_none());
+
+            assert!(!reason.covers_all());
+
+            // 维护窗口的开始和结束都会记录
+            pause.record_windows_at(TimestampNanos::from(15));
+            assert_eq!(pause.history.len(), 1);
+            assert!(matches!(pause.history[0].action, PauseAction::Paused(_)));
+            assert_eq!(pause.history[0].created, TimestampNanos::from(10));
+            pause.record_windows_at(TimestampNanos::from(15));
+            assert_eq!(pause.history.len(), 1);
+            pause.record_windows_at(TimestampNanos::from(25));
+            assert_eq!(pause.history.len(), 2);
+            assert_eq!(pause.history[1].created, TimestampNanos::from(20));
+
+            pause
+                .schedule_at(
+                    TimestampNanos::from(20),
+                    TimestampNanos::from(40),
+                    "incident".to_string(),
+                    None,
+                    TimestampNanos::from(30),
+                )
+                .unwrap();
+            assert_eq!(
+                pause
+                    .unschedule_at(TimestampNanos::from(20), TimestampNanos::from(35))
+                    .len(),
+                1
+            );
+            assert!(pause.windows.is_empty());
+            assert_eq!(pause.history.len(), 4);
+            assert_eq!(pause.history[2].created, TimestampNanos::from(30));
+            assert!(matches!(pause.history[3].action, PauseAction::Resumed(_)));
+            assert_eq!(pause.history[3].created, TimestampNanos::from(35));
+        }
+
+        #[test]
+        fn group_only_pause_does_not_pause_the_whole_canister() {
+            let groups = HashSet::from(["transfer".to_string()]);
+            let pause = Pause::new(Some(
+                PauseReason::new_at("transfer".to_string(), TimestampNanos::from(1)).with_groups(groups),
+            ));
+            assert!(pause.pause_is_running());
+            assert!(pause.pause_must_be_running().is_ok());
+            assert!(pause.pause_must_be_paused().is_err());
+            assert!(pause.pause_must_be_running_for("transfer").is_err());
+            assert!(pause.pause_must_be_running_for("query").is_ok());
+
+            let pause = Pause::new(Some(PauseReason::new_at("all".to_string(), TimestampNanos::from(1))));
+            assert!(pause.pause_is_paused());
+            assert!(pause.pause_must_be_running().is_err());
+        }
+
+        #[test]
+        fn low_cycles_triggers_pause_once() {
+            let mut pause = Pause::default();
+            let caller = Principal::management_canister();
+            assert!(!pause.check_cycles_at(0, caller, TimestampNanos::from(1)));
+
+            pause.pause_set_cycles_threshold(Some(PauseCyclesThreshold {
+                threshold: 100,
+                message: "low cycles".to_string(),
+            }));
+            assert!(!pause.check_cycles_at(100, caller, TimestampNanos::from(1)));
+            assert!(pause.check_cycles_at(99, caller, TimestampNanos::from(2)));
+            assert!(!pause.check_cycles_at(50, caller, TimestampNanos::from(3)));
+            assert!(pause.pause_is_paused());
+            assert_eq!(pause.pause_query().as_ref().unwrap().paused_at, TimestampNanos::from(2));
+            assert_eq!(pause.pause_query().as_ref().unwrap().category, PauseCategory::Incident);
+            assert_eq!(pause.history.len(), 1);
+        }
+
+        #[test]
+        fn keeps_bounded_history_of_pause_and_resume() {
+            let caller = Principal::management_canister();
+            let mut pause = Pause {
+                history_limit: 2,
+                ..Default::default()
+            };
+            pause.replace_at(caller, None, TimestampNanos::from(0));
+            assert!(pause.history.is_empty());
+
+            let reason = PauseReason::new_at("upgrade".to_string(), TimestampNanos::from(1))
+                .with_category(PauseCategory::Upgrade);
+            pause.replace_at(caller, Some(reason), TimestampNanos::from(1));
+            pause.replace_at(caller, None, TimestampNanos::from(2));
+            pause.replace_at(
+                caller,
+                Some(PauseReason::new_at("incident".to_string(), TimestampNanos::from(3))),
+                TimestampNanos::from(3),
+            );
+
+            assert_eq!(pause.history.len(), 2);
+            let Some(PauseEvent {
+                action: PauseAction::Resumed(previous),
+                ..
+            }) = pause.history.first()
+            else {
+                panic!("expected the resume event to be kept")
+            };
+            assert_eq!(previous.category, PauseCategory::Upgrade);
+
+            let page = pause
+                .pause_history_by_page(&QueryPage { page: 1, size: 1 }, 10)
+                .unwrap();
+            assert_eq!(page.total, 2);
+            assert_eq!(page.data[0].created, TimestampNanos::from(3));
+        }
     }
 }
@@ -115,6 +115,7 @@ pub mod basic {
     use std::{
         collections::{HashMap, HashSet},
         fmt::Display,
+        hash::Hash,
     };
 
     use candid::CandidType;
@@ -126,7 +127,7 @@ pub mod basic {
     };
 
     /// 被管理的用户类型
-    #[derive(CandidType, Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
+    #[derive(CandidType, Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
     pub enum Permission {
         /// 授权类型 默认没有该权限 只有被加入的用户才有该权限
         Permitted(String),
@@ -418,6 +419,173 @@ pub mod basic {
         }
     }
 
+    // ================= 配置导入导出 =================
+
+    /// 权限配置文档
+    ///
+    /// 所有集合都按顺序排列，相同的权限状态导出的文档完全一致，便于存档、比较和人工审阅
+    #[derive(CandidType, Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+    pub struct PermissionsDocument {
+        /// 所有权限种类
+        pub permissions: Vec<Permission>,
+        /// 用户分配的特别权限
+        pub user_permissions: Vec<(UserId, Vec<Permission>)>,
+        /// 某角色对权限的限制
+        pub role_permissions: Vec<(String, Vec<Permission>)>,
+        /// 用户被授权的角色
+        pub user_roles: Vec<(UserId, Vec<String>)>,
+    }
+
+    fn sorted<T: Ord + Clone>(items: &HashSet<T>) -> Vec<T> {
+        let mut items: Vec<T> = items.iter().cloned().collect();
+        items.sort();
+        items
+    }
+
+    fn sorted_entries<K: Ord + Clone, T: Ord + Clone>(entries: &HashMap<K, HashSet<T>>) -> Vec<(K, Vec<T>)> {
+        let mut entries: Vec<(K, Vec<T>)> = entries.iter().map(|(k, v)| (k.clone(), sorted(v))).collect();
+        entries.sort_by(|a, b| a.0.cmp(&b.0));
+        entries
+    }
+
+    fn collect_entries<K: Eq + Hash + Clone, T: Eq + Hash + Clone>(entries: &[(K, Vec<T>)]) -> HashMap<K, HashSet<T>> {
+        entries
+            .iter()
+            .map(|(k, v)| (k.clone(), v.iter().cloned().collect()))
+            .collect()
+    }
+
+    // 按键排序后比较两组数据，仅输出有差异的键
+    fn diff_entries<K: Eq + Hash + Ord + Clone, T: Eq + Hash>(
+        current: &HashMap<K, HashSet<T>>,
+        target: &HashMap<K, HashSet<T>>,
+    ) -> Vec<K> {
+        let mut keys: Vec<&K> = current.keys().chain(target.keys()).collect();
+        keys.sort();
+        keys.dedup();
+        keys.into_iter()
+            .filter(|key| current.get(*key) != target.get(*key))
+            .cloned()
+            .collect()
+    }
+
+    impl PermissionsDocument {
+        /// 导出为 json 文本
+        pub fn to_json(&self) -> Result<String, serde_json::Error> {
+            serde_json::to_string_pretty(self)
+        }
+        /// 从 json 文本导入
+        pub fn from_json(json: &str) -> Result<Self, serde_json::Error> {
+            serde_json::from_str(json)
+        }
+        /// 导出为 candid 编码
+        pub fn to_candid(&self) -> Result<Vec<u8>, candid::Error> {
+            candid::encode_one(self)
+        }
+        /// 从 candid 编码导入
+        pub fn from_candid(bytes: &[u8]) -> Result<Self, candid::Error> {
+            candid::decode_one(bytes)
+        }
+    }
+
+    impl Display for PermissionsDocument {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            let join = |items: &[Permission]| items.iter().map(|p| p.to_string()).collect::<Vec<_>>().join(",");
+            for permission in &self.permissions {
+                writeln!(f, "permission {permission}")?;
+            }
+            for (role, permissions) in &self.role_permissions {
+                writeln!(f, "role {role} permissions: [{}]", join(permissions))?;
+            }
+            for (user_id, permissions) in &self.user_permissions {
+                writeln!(f, "user {} permissions: [{}]", user_id.to_text(), join(permissions))?;
+            }
+            for (user_id, roles) in &self.user_roles {
+                writeln!(f, "user {} roles: [{}]", user_id.to_text(), roles.join(","))?;
+            }
+            Ok(())
+        }
+    }
+
+    impl From<&Permissions> for PermissionsDocument {
+        fn from(value: &Permissions) -> Self {
+            PermissionsDocument {
+                permissions: sorted(&value.permissions),
+                user_permissions: sorted_entries(&value.user_permissions),
+                role_permissions: sorted_entries(&value.role_permissions),
+                user_roles: sorted_entries(&value.user_roles),
+            }
+        }
+    }
+
+    impl From<&PermissionsDocument> for Permissions {
+        fn from(value: &PermissionsDocument) -> Self {
+            Permissions {
+                permissions: value.permissions.iter().cloned().collect(),
+                user_permissions: collect_entries(&value.user_permissions),
+                role_permissions: collect_entries(&value.role_permissions),
+                user_roles: collect_entries(&value.user_roles),
+            }
+        }
+    }
+
+    impl Permissions {
+        /// 导出权限配置文档
+        pub fn permission_export(&self) -> PermissionsDocument {
+            self.into()
+        }
+
+        /// 计算从当前状态变更到目标文档所需的最少更新操作
+        ///
+        /// 操作顺序为: 新增或修改角色、删除角色、更新用户权限、更新用户角色。
+        /// 删除角色会同时从用户身上移除该角色，因此用户角色的比较基于删除之后的状态。
+        /// 权限种类由代码定义，应通过 [`Permissable::permission_reset`] 维护，不会出现在结果中
+        pub fn permission_diff(&self, target: &PermissionsDocument) -> Vec<PermissionUpdatedArg<Permission>> {
+            let target: Permissions = target.into();
+            let mut args = Vec::new();
+
+            let roles = diff_entries(&self.role_permissions, &target.role_permissions);
+            let (upserted, removed): (Vec<String>, Vec<String>) = roles
+                .into_iter()
+                .partition(|role| target.role_permissions.contains_key(role));
+            for role in upserted {
+                let permissions = target.role_permissions.get(&role).cloned();
+                args.push(PermissionUpdatedArg::UpdateRolePermission(role, permissions));
+            }
+            for role in removed.iter() {
+                args.push(PermissionUpdatedArg::UpdateRolePermission(role.clone(), None));
+            }
+
+            for user_id in diff_entries(&self.user_permissions, &target.user_permissions) {
+                let permissions = target.user_permissions.get(&user_id).cloned();
+                args.push(PermissionUpdatedArg::UpdateUserPermission(user_id, permissions));
+            }
+
+            let mut user_roles = self.user_roles.clone();
+            user_roles
+                .values_mut()
+                .for_each(|roles| roles.retain(|role| !removed.contains(role)));
+            for user_id in diff_entries(&user_roles, &target.user_roles) {
+                let roles = target.user_roles.get(&user_id).cloned();
+                args.push(PermissionUpdatedArg::UpdateUserRole(user_id, roles));
+            }
+
+            args
+        }
+
+        /// 导入权限配置文档，整个过程是原子的，任意一项失败时原权限状态完全不变
+        ///
+        /// 返回实际应用的更新操作
+        pub fn permission_import(
+            &mut self,
+            target: &PermissionsDocument,
+        ) -> Result<Vec<PermissionUpdatedArg<Permission>>, PermissionUpdatedError<Permission>> {
+            let args = self.permission_diff(target);
+            self.permission_update(args.clone())?;
+            Ok(args)
+        }
+    }
+
     // ================= 工具方法 =================
 
     /// 解析所有权限
@@ -455,7 +623,7 @@ pub mod basic {
 
         use candid::Principal;
 
-        use super::{Permission, Permissions};
+        use super::{Permission, Permissions, PermissionsDocument};
         use crate::functions::permission::{Permissable, PermissionUpdatedArg, PermissionUpdatedError};
 
         fn permissions() -> Permissions {
@@ -499,5 +667,50 @@ pub mod basic {
 
             assert!(permissions.permission_has(&user, &read));
         }
+
+        #[test]
+        fn import_applies_minimal_diff_and_round_trips_documents() {
+            let user = Principal::anonymous();
+            let read = Permission::by_permit("read");
+            let mut current = permissions();
+            current
+                .permission_update(vec![
+                    PermissionUpdatedArg::UpdateRolePermission("old".to_string(), Some(HashSet::new())),
+                    PermissionUpdatedArg::UpdateUserRole(user, Some(HashSet::from(["old".to_string()]))),
+                ])
+                .unwrap();
+
+            let mut target = permissions();
+            target
+                .role_permissions
+                .insert("reader".to_string(), HashSet::from([read.clone()]));
+            target.user_roles.insert(user, HashSet::from(["reader".to_string()]));
+            let document = PermissionsDocument::from_json(&target.permission_export().to_json().unwrap()).unwrap();
+            assert_eq!(
+                PermissionsDocument::from_candid(&document.to_candid().unwrap()).unwrap(),
+                document
+            );
+
+            assert_eq!(current.permission_diff(&document).len(), 3);
+            current.permission_import(&document).unwrap();
+            assert_eq!(current.permission_export(), document);
+            assert!(current.permission_has(&user, &read));
+            assert!(current.permission_diff(&document).is_empty());
+        }
+
+        #[test]
+        fn import_is_atomic_when_document_uses_unknown_permission() {
+            let mut current = permissions();
+            let mut target = permissions();
+            target.user_permissions.insert(
+                Principal::anonymous(),
+                HashSet::from([Permission::by_permit("missing")]),
+            );
+
+            let result = current.permission_import(&target.permission_export());
+
+            assert!(matches!(result, Err(PermissionUpdatedError::InvalidPermission(_))));
+            assert!(current.user_permissions.is_empty());
+        }
     }
 }
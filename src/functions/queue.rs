@@ -0,0 +1,379 @@
+//! 失败重试的任务队列
+
+/*
+
+任务失败后按指数退避重试，超过次数后进入死信列表，由管理员查看、重试或丢弃
+
+#[ic_cdk::post_upgrade]
+fn post_upgrade() {
+    // 升级前执行中的任务重新排队
+    with_mut_state(|s| s.queue.queue_reset_in_flight());
+}
+
+async fn execute_due_tasks() {
+    for task in with_mut_state(|s| s.queue.queue_take_due(10)) {
+        let result = transfer(&task.payload).await.map_err(|err| err.to_string());
+        with_mut_state(|s| s.queue.queue_complete(task.id, result));
+    }
+}
+
+*/
+
+use crate::types::{PageData, QueryPage, QueryPageError};
+
+// ================== 功能 ==================
+
+/// 任务队列
+pub trait Queueable<Task, Payload> {
+    // 查询
+
+    /// 等待执行的任务，包括执行中的任务
+    fn queue_pending(&self) -> &[Task];
+    /// 死信列表 正序
+    fn queue_dead_letters(&self) -> &[Task];
+
+    // 修改
+
+    /// 添加任务，返回任务 id
+    fn queue_push(&mut self, payload: Payload) -> u64;
+    /// 取出到期的任务并标记为执行中，最多取出 limit 个
+    fn queue_take_due(&mut self, limit: usize) -> Vec<Task>;
+    /// 记录执行结果，失败则安排重试或者进入死信列表
+    fn queue_complete(&mut self, id: u64, result: Result<(), String>);
+    /// 升级后执行中的任务已经中断，重新标记为等待执行
+    fn queue_reset_in_flight(&mut self);
+    /// 将死信列表中的任务重新排队，重试次数清零
+    fn queue_retry_dead_letter(&mut self, id: u64) -> Result<(), String>;
+    /// 丢弃死信列表中的任务，返回实际丢弃的数量
+    fn queue_drop_dead_letters(&mut self, ids: &[u64]) -> u64;
+
+    /// 分页查询死信列表，最新的在前
+    fn queue_dead_letters_by_page(
+        &self,
+        page: &QueryPage,
+        max_page_size: u32,
+    ) -> Result<PageData<&Task>, QueryPageError> {
+        page.query_desc_by_list(self.queue_dead_letters(), max_page_size)
+    }
+}
+
+// ================== 简单实现 ==================
+
+/// 任务队列简单实现
+pub mod basic {
+    use candid::CandidType;
+    use serde::{Deserialize, Serialize};
+
+    use crate::{
+        functions::types::Queueable,
+        types::{DurationNanos, TimestampNanos},
+    };
+
+    /// 重试策略
+    #[derive(CandidType, Serialize, Deserialize, Debug, Clone)]
+    pub struct RetryPolicy {
+        /// 最多执行次数，包括第一次
+        pub max_attempts: u32,
+        /// 第一次重试前的等待时间，之后每次翻倍
+        pub base_delay: DurationNanos,
+        /// 最长等待时间
+        pub max_delay: DurationNanos,
+        /// 抖动比例 0-100，实际等待时间在 `(100 - jitter)%` 到 `100%` 之间
+        pub jitter_percent: u8,
+    }
+
+    impl Default for RetryPolicy {
+        fn default() -> Self {
+            Self {
+                max_attempts: 5,
+                base_delay: 1_000_000_000_u128.into(),    // 1 秒
+                max_delay: 3_600_000_000_000_u128.into(), // 1 小时
+                jitter_percent: 20,
+            }
+        }
+    }
+
+    // 简单的伪随机数，只用于打散重试时间
+    fn splitmix64(mut x: u64) -> u64 {
+        x = x.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        x = (x ^ (x >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        x = (x ^ (x >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        x ^ (x >> 31)
+    }
+
+    impl RetryPolicy {
+        /// 第 attempts 次失败后需要等待的时间
+        pub fn delay(&self, attempts: u32, seed: u64) -> DurationNanos {
+            let exponent = attempts.saturating_sub(1).min(127);
+            let delay = self
+                .base_delay
+                .into_inner()
+                .saturating_mul(1_u128 << exponent)
+                .min(self.max_delay.into_inner());
+            // 先乘后除避免小于 100ns 时被截断，溢出时再先除后乘
+            let factor = u128::from(self.jitter_percent.min(100)) * u128::from(splitmix64(seed) % 101);
+            let reduced = delay
+                .checked_mul(factor)
+                .map_or(delay / 10_000 * factor, |value| value / 10_000)
+                .min(delay);
+            (delay - reduced).into()
+        }
+    }
+
+    /// 队列中的任务
+    #[derive(CandidType, Serialize, Deserialize, Debug, Clone)]
+    pub struct QueueTask<T> {
+        /// 任务 id
+        pub id: u64,
+        /// 创建时间
+        pub created: TimestampNanos,
+        /// 任务内容
+        pub payload: T,
+        /// 已经执行的次数
+        pub attempts: u32,
+        /// 下一次可以执行的时间
+        pub next_attempt_at: TimestampNanos,
+        /// 是否执行中
+        pub in_flight: bool,
+        /// 最近一次失败的时间和错误
+        pub last_error: Option<(TimestampNanos, String)>,
+    }
+
+    /// 持久化的任务队列
+    #[derive(CandidType, Serialize, Deserialize, Debug, Clone)]
+    pub struct TaskQueue<T> {
+        /// 重试策略
+        pub policy: RetryPolicy,
+        /// 死信列表最多保留的条数
+        pub dead_letter_limit: u64,
+        /// 下一个未使用的 id
+        pub next_id: u64,
+        /// 等待执行的任务
+        pub pending: Vec<QueueTask<T>>,
+        /// 死信列表
+        pub dead_letters: Vec<QueueTask<T>>,
+    }
+
+    impl<T> Default for TaskQueue<T> {
+        fn default() -> Self {
+            Self {
+                policy: Default::default(),
+                dead_letter_limit: 1024,
+                next_id: 0,
+                pending: Vec::new(),
+                dead_letters: Vec::new(),
+            }
+        }
+    }
+
+    impl<T: Clone> TaskQueue<T> {
+        fn push_at(&mut self, payload: T, now: TimestampNanos) -> u64 {
+            let id = self.next_id;
+            self.next_id += 1;
+            self.pending.push(QueueTask {
+                id,
+                created: now,
+                payload,
+                attempts: 0,
+                next_attempt_at: now,
+                in_flight: false,
+                last_error: None,
+            });
+            id
+        }
+
+        fn take_due_at(&mut self, limit: usize, now: TimestampNanos) -> Vec<QueueTask<T>> {
+            let mut due: Vec<&mut QueueTask<T>> = self
+                .pending
+                .iter_mut()
+                .filter(|task| !task.in_flight && task.next_attempt_at <= now)
+                .collect();
+            due.sort_by_key(|task| (task.next_attempt_at, task.id));
+            due.into_iter()
+                .take(limit)
+                .map(|task| {
+                    task.in_flight = true;
+                    task.attempts = task.attempts.saturating_add(1);
+                    task.clone()
+                })
+                .collect()
+        }
+
+        fn complete_at(&mut self, id: u64, result: Result<(), String>, now: TimestampNanos) {
+            let Some(index) = self.pending.iter().position(|task| task.id == id) else {
+                return;
+            };
+            let Err(error) = result else {
+                self.pending.remove(index);
+                return;
+            };
+            let task = &mut self.pending[index];
+            task.in_flight = false;
+            task.last_error = Some((now, error));
+            if task.attempts < self.policy.max_attempts {
+                let seed = task.id ^ u64::from(task.attempts) ^ (now.into_inner() as u64);
+                let delay = self.policy.delay(task.attempts, seed).into_inner();
+                let delay = i128::try_from(delay).unwrap_or(i128::MAX);
+                task.next_attempt_at = now.into_inner().saturating_add(delay).into();
+                return;
+            }
+            let task = self.pending.remove(index);
+            self.push_dead_letter(task);
+        }
+
+        fn push_dead_letter(&mut self, task: QueueTask<T>) {
+            let limit = usize::try_from(self.dead_letter_limit).unwrap_or(usize::MAX);
+            if limit == 0 {
+                return;
+            }
+            if limit <= self.dead_letters.len() {
+                self.dead_letters.drain(..self.dead_letters.len() - limit + 1);
+            }
+            self.dead_letters.push(task);
+        }
+
+        fn retry_dead_letter_at(&mut self, id: u64, now: TimestampNanos) -> Result<(), String> {
+            let index = self
+                .dead_letters
+                .iter()
+                .position(|task| task.id == id)
+                .ok_or_else(|| format!("Dead letter {id} is not found."))?;
+            let mut task = self.dead_letters.remove(index);
+            task.attempts = 0;
+            task.next_attempt_at = now;
+            task.in_flight = false;
+            self.pending.push(task);
+            Ok(())
+        }
+    }
+
+    impl<T: Clone> Queueable<QueueTask<T>, T> for TaskQueue<T> {
+        // 查询
+        fn queue_pending(&self) -> &[QueueTask<T>] {
+            &self.pending
+        }
+        fn queue_dead_letters(&self) -> &[QueueTask<T>] {
+            &self.dead_letters
+        }
+
+        // 修改
+        fn queue_push(&mut self, payload: T) -> u64 {
+            self.push_at(payload, crate::times::now())
+        }
+        fn queue_take_due(&mut self, limit: usize) -> Vec<QueueTask<T>> {
+            self.take_due_at(limit, crate::times::now())
+        }
+        fn queue_complete(&mut self, id: u64, result: Result<(), String>) {
+            self.complete_at(id, result, crate::times::now())
+        }
+        fn queue_reset_in_flight(&mut self) {
+            self.pending.iter_mut().for_each(|task| task.in_flight = false);
+        }
+        fn queue_retry_dead_letter(&mut self, id: u64) -> Result<(), String> {
+            self.retry_dead_letter_at(id, crate::times::now())
+        }
+        fn queue_drop_dead_letters(&mut self, ids: &[u64]) -> u64 {
+            let before = self.dead_letters.len();
+            self.dead_letters.retain(|task| !ids.contains(&task.id));
+            (before - self.dead_letters.len()) as u64
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::{RetryPolicy, TaskQueue};
+        use crate::{functions::types::Queueable, types::TimestampNanos};
+
+        fn queue() -> TaskQueue<String> {
+            TaskQueue {
+                policy: RetryPolicy {
+                    max_attempts: 2,
+                    base_delay: 10_u128.into(),
+                    max_delay: 15_u128.into(),
+                    jitter_percent: 0,
+                },
+                ..Default::default()
+            }
+        }
+
+        #[test]
+        fn backoff_grows_exponentially_within_bounds() {
+            let policy = RetryPolicy {
+                max_attempts: 10,
+                base_delay: 100_u128.into(),
+                max_delay: 1_000_u128.into(),
+                jitter_percent: 50,
+            };
+            for seed in 0..32 {
+                let first = policy.delay(1, seed).into_inner();
+                assert!((50..=100).contains(&first));
+                let third = policy.delay(3, seed).into_inner();
+                assert!((200..=400).contains(&third));
+                assert!(policy.delay(64, seed).into_inner() <= 1_000);
+            }
+
+            // 小于 100ns 时抖动仍然生效，极大的等待时间不会溢出
+            let policy = RetryPolicy {
+                max_attempts: u32::MAX,
+                base_delay: 50_u128.into(),
+                max_delay: u128::MAX.into(),
+                jitter_percent: 100,
+            };
+            assert!(
+                (0..50)
+                    .map(|seed| policy.delay(1, seed).into_inner())
+                    .any(|delay| delay < 50)
+            );
+            for seed in 0..32 {
+                let mut queue = TaskQueue {
+                    policy: policy.clone(),
+                    ..Default::default()
+                };
+                let id = queue.push_at("overflow".to_string(), TimestampNanos::from(0));
+                queue.pending[0].attempts = 199;
+                queue.take_due_at(1, TimestampNanos::from(seed as i128));
+                queue.complete_at(id, Err("ledger".to_string()), TimestampNanos::from(seed as i128));
+                assert!(queue.pending[0].next_attempt_at >= TimestampNanos::from(seed as i128));
+            }
+        }
+
+        #[test]
+        fn failed_task_is_retried_then_moved_to_dead_letters() {
+            let mut queue = queue();
+            let id = queue.push_at("transfer".to_string(), TimestampNanos::from(0));
+
+            let due = queue.take_due_at(10, TimestampNanos::from(0));
+            assert_eq!(due.len(), 1);
+            assert!(queue.take_due_at(10, TimestampNanos::from(0)).is_empty());
+
+            queue.complete_at(id, Err("ledger".to_string()), TimestampNanos::from(1));
+            assert!(queue.take_due_at(10, TimestampNanos::from(10)).is_empty());
+            assert_eq!(queue.take_due_at(10, TimestampNanos::from(11)).len(), 1);
+
+            queue.complete_at(id, Err("ledger".to_string()), TimestampNanos::from(12));
+            assert!(queue.pending.is_empty());
+            assert_eq!(queue.queue_dead_letters().len(), 1);
+
+            queue.retry_dead_letter_at(id, TimestampNanos::from(20)).unwrap();
+            assert_eq!(queue.pending[0].attempts, 0);
+            assert_eq!(queue.take_due_at(10, TimestampNanos::from(20)).len(), 1);
+            queue.complete_at(id, Ok(()), TimestampNanos::from(21));
+            assert!(queue.pending.is_empty());
+            assert!(queue.retry_dead_letter_at(id, TimestampNanos::from(22)).is_err());
+        }
+
+        #[test]
+        fn in_flight_tasks_are_requeued_after_upgrade_and_dead_letters_can_be_dropped() {
+            let mut queue = queue();
+            let id = queue.push_at("transfer".to_string(), TimestampNanos::from(0));
+            queue.take_due_at(10, TimestampNanos::from(0));
+            queue.queue_reset_in_flight();
+            assert_eq!(queue.take_due_at(10, TimestampNanos::from(0)).len(), 1);
+
+            // 中断的那次也计入执行次数
+            queue.complete_at(id, Err("ledger".to_string()), TimestampNanos::from(1));
+            assert_eq!(queue.queue_drop_dead_letters(&[id, 99]), 1);
+            assert!(queue.queue_dead_letters().is_empty());
+        }
+    }
+}
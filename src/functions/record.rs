@@ -35,17 +35,26 @@ pub trait Searchable<Record> {
     fn test(&self, record: &Record) -> bool;
 }
 
+/// 任意函数都可以作为查询条件
+impl<Record, F: Fn(&Record) -> bool> Searchable<Record> for F {
+    fn test(&self, record: &Record) -> bool {
+        self(record)
+    }
+}
+
 /// 可以记录的操作
-pub trait Recordable<Record, RecordTopic, Search: Searchable<Record>> {
+///
+/// 记录内容和执行结果默认是文本，也可以是自定义的类型
+pub trait Recordable<Record, RecordTopic, Search: Searchable<Record>, Content = String, Outcome = String> {
     // 查询
     /// 查询所有
     fn record_find_all(&self) -> &[Record];
 
     // 修改
     /// 插入记录
-    fn record_push(&mut self, caller: CallerId, topic: RecordTopic, content: String) -> RecordId;
+    fn record_push(&mut self, caller: CallerId, topic: RecordTopic, content: Content) -> RecordId;
     /// 更新记录
-    fn record_update(&mut self, record_id: RecordId, result: String);
+    fn record_update(&mut self, record_id: RecordId, result: Outcome);
     /// 按 id 批量删除记录，返回实际删除的记录数量
     fn record_delete(&mut self, ids: &HashSet<RecordId>) -> u64;
 
@@ -64,19 +73,91 @@ pub trait Recordable<Record, RecordTopic, Search: Searchable<Record>> {
     }
 }
 
+// 超出保留上限时淘汰最早的记录，返回被淘汰的记录
+fn push_with_retention<R>(records: &mut Vec<R>, retention_limit: u64, record: R) -> Vec<R> {
+    if retention_limit == 0 {
+        return vec![record];
+    }
+
+    let retention_limit = usize::try_from(retention_limit).unwrap_or(usize::MAX);
+    let mut evicted = Vec::new();
+    if retention_limit <= records.len() {
+        let remove_count = records.len() - retention_limit + 1;
+        evicted = records.drain(..remove_count).collect();
+    }
+    records.push(record);
+    evicted
+}
+
+// 范围过滤，依次为包含下界和包含上界
+fn in_range<T: Ord>(value: &T, range: &Option<(Option<T>, Option<T>)>) -> bool {
+    let Some((min, max)) = range else {
+        return true;
+    };
+    min.as_ref().is_none_or(|min| min <= value) && max.as_ref().is_none_or(|max| value <= max)
+}
+
+// ================== 归档 ==================
+
+/*
+
+开启归档后，超出保留上限被淘汰的记录会进入待归档列表，分批推送到归档罐子
+
+归档罐子需要实现如下接口:
+
+type RecordArchiveGetArg = record { start : nat64; length : nat64 };
+service : {
+    append_records : (vec Record) -> ();
+    get_records : (RecordArchiveGetArg) -> (vec Record) query;
+}
+
+async fn archive_records() {
+    let Some((canister_id, batch)) = with_mut_state(|s| s.records.record_archive_take()) else {
+        return;
+    };
+    let result = ic_canister_kit::functions::record::push_record_archive(canister_id, &batch).await;
+    with_mut_state(|s| s.records.record_archive_done(canister_id, batch, result));
+}
+
+升级会打断正在推送的一批，post_upgrade 中调用 record_archive_abort 后即可重新推送
+
+客户端先查询 record_locations 得到各归档罐子保存的 id 区间，再到对应罐子调用 get_records，
+等待归档的记录不在分页查询中，通过本罐子查询
+
+#[ic_cdk::query]
+fn get_pending_records(arg: RecordArchiveGetArg) -> Vec<Record> {
+    with_state(|s| s.records.record_archive_pending(&arg, 1000).to_vec())
+}
+
+*/
+
+/// 归档罐子接收记录的方法
+pub const RECORD_ARCHIVE_APPEND_METHOD: &str = "append_records";
+/// 归档罐子查询记录的方法
+pub const RECORD_ARCHIVE_GET_METHOD: &str = "get_records";
+
+/// 推送一批记录到归档罐子
+pub async fn push_record_archive(
+    canister_id: crate::identity::CanisterId,
+    records: &[basic::Record],
+) -> crate::canister::types::CanisterCallResult<()> {
+    crate::canister::call::call_canister(canister_id, RECORD_ARCHIVE_APPEND_METHOD, (records,)).await
+}
+
 // ================== 简单实现 ==================
 
 /// 记录功能简单实现
 pub mod basic {
-    use std::collections::HashSet;
+    use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
 
     use candid::CandidType;
     use serde::{Deserialize, Serialize};
 
     use crate::{
+        canister::types::CanisterCallError,
         functions::types::{RecordId, Recordable, Searchable},
-        identity::CallerId,
-        types::TimestampNanos,
+        identity::{CallerId, CanisterId},
+        types::{PageData, QueryPage, QueryPageError, TimestampNanos},
     };
 
     /// 记录主题
@@ -122,6 +203,9 @@ pub mod basic {
         pub topic: Option<HashSet<RecordTopic>>,
         /// 内容过滤
         pub content: Option<String>,
+        /// 全文检索关键词，内容需要包含所有关键词
+        #[serde(default)]
+        pub keywords: Option<Vec<String>>,
     }
 
     impl Searchable<Record> for RecordSearch {
@@ -168,12 +252,135 @@ pub mod basic {
             {
                 return false;
             }
+            if let Some(keywords) = &self.keywords {
+                let tokens = tokenize(&record.content);
+                if !keywords
+                    .iter()
+                    .flat_map(|keyword| tokenize(keyword))
+                    .all(|token| tokens.contains(&token))
+                {
+                    return false;
+                }
+            }
             true
         }
     }
 
+    // 内容分词，按非字母数字字符切分并转为小写
+    fn tokenize(content: &str) -> HashSet<String> {
+        content
+            .split(|c: char| !c.is_alphanumeric())
+            .filter(|token| !token.is_empty())
+            .map(|token| token.to_lowercase())
+            .collect()
+    }
+
+    /// 记录索引，按调用人、主题、时间段和内容分词维护，不参与序列化
+    #[derive(Debug, Clone, Default)]
+    pub struct RecordIndex {
+        size: u64, // 已索引的记录数量，与记录列表长度不一致则索引失效
+        callers: HashMap<CallerId, BTreeSet<RecordId>>,
+        topics: HashMap<RecordTopic, BTreeSet<RecordId>>,
+        buckets: BTreeMap<i128, BTreeSet<RecordId>>,
+        tokens: HashMap<String, BTreeSet<RecordId>>,
+    }
+
+    impl RecordIndex {
+        const BUCKET_NANOS: i128 = 60 * 60 * 1_000_000_000; // 每小时一个时间段
+
+        fn bucket(created: TimestampNanos) -> i128 {
+            created.into_inner().div_euclid(Self::BUCKET_NANOS)
+        }
+
+        fn rebuild(records: &[Record]) -> Self {
+            let mut index = Self::default();
+            for record in records {
+                index.insert(record);
+            }
+            index
+        }
+
+        fn insert(&mut self, record: &Record) {
+            self.size += 1;
+            self.callers.entry(record.caller).or_default().insert(record.id);
+            self.topics.entry(record.topic).or_default().insert(record.id);
+            self.buckets
+                .entry(Self::bucket(record.created))
+                .or_default()
+                .insert(record.id);
+            for token in tokenize(&record.content) {
+                self.tokens.entry(token).or_default().insert(record.id);
+            }
+        }
+
+        fn remove(&mut self, record: &Record) {
+            fn remove_id<K: Eq + std::hash::Hash>(map: &mut HashMap<K, BTreeSet<RecordId>>, key: &K, id: &RecordId) {
+                if let Some(ids) = map.get_mut(key) {
+                    ids.remove(id);
+                    if ids.is_empty() {
+                        map.remove(key);
+                    }
+                }
+            }
+
+            self.size = self.size.saturating_sub(1);
+            remove_id(&mut self.callers, &record.caller, &record.id);
+            remove_id(&mut self.topics, &record.topic, &record.id);
+            let bucket = Self::bucket(record.created);
+            if let Some(ids) = self.buckets.get_mut(&bucket) {
+                ids.remove(&record.id);
+                if ids.is_empty() {
+                    self.buckets.remove(&bucket);
+                }
+            }
+            for token in tokenize(&record.content) {
+                remove_id(&mut self.tokens, &token, &record.id);
+            }
+        }
+
+        // 根据检索条件计算候选 id，没有可用的索引条件则返回 None
+        fn candidates(&self, search: &RecordSearch) -> Option<BTreeSet<RecordId>> {
+            fn union<'a>(sets: impl Iterator<Item = Option<&'a BTreeSet<RecordId>>>) -> BTreeSet<RecordId> {
+                sets.flatten().flatten().copied().collect()
+            }
+            fn intersect(
+                candidates: Option<BTreeSet<RecordId>>,
+                ids: BTreeSet<RecordId>,
+            ) -> Option<BTreeSet<RecordId>> {
+                Some(match candidates {
+                    Some(candidates) => candidates.intersection(&ids).copied().collect(),
+                    None => ids,
+                })
+            }
+
+            let mut candidates = None;
+            if let Some(callers) = &search.caller {
+                candidates = intersect(candidates, union(callers.iter().map(|caller| self.callers.get(caller))));
+            }
+            if let Some(topics) = &search.topic {
+                candidates = intersect(candidates, union(topics.iter().map(|topic| self.topics.get(topic))));
+            }
+            if let Some((min, max)) = &search.created_at_nanos_range {
+                let min = min.map_or(i128::MIN, Self::bucket);
+                let max = max.map_or(i128::MAX, Self::bucket);
+                if min <= max {
+                    candidates = intersect(
+                        candidates,
+                        union(self.buckets.range(min..=max).map(|(_, ids)| Some(ids))),
+                    );
+                } else {
+                    candidates = Some(BTreeSet::new());
+                }
+            }
+            for token in search.keywords.iter().flatten().flat_map(|keyword| tokenize(keyword)) {
+                candidates = intersect(candidates, self.tokens.get(&token).cloned().unwrap_or_default());
+            }
+            candidates
+        }
+    }
+
     /// 持久化的记录对象
-    #[derive(CandidType, Serialize, Deserialize, Debug, Clone)]
+    #[derive(Serialize, Deserialize, Debug, Clone)]
     pub struct Records {
         /// 最多保留的记录条数
         #[serde(alias = "max")]
@@ -185,6 +392,41 @@ pub mod basic {
         pub next_id: RecordId,
         /// 当前保留的记录列表
         pub records: Vec<Record>,
+        /// 归档设置与状态
+        #[serde(default)]
+        pub archiving: RecordArchiving,
+        /// 检索索引，不参与序列化，升级后在 post_upgrade 中调用 record_reindex 重建，否则在下次写入时重建
+        #[serde(skip)]
+        pub index: RecordIndex,
+    }
+
+    // Records 的 candid 类型，不包含检索索引
+    #[derive(CandidType)]
+    struct RecordsCandid<'a> {
+        retention_limit: u64,
+        retention_evicted_count: u64,
+        next_id: RecordId,
+        records: &'a Vec<Record>,
+        archiving: &'a RecordArchiving,
+    }
+
+    impl CandidType for Records {
+        fn _ty() -> candid::types::Type {
+            RecordsCandid::ty()
+        }
+        fn idl_serialize<S>(&self, serializer: S) -> Result<(), S::Error>
+        where
+            S: candid::types::Serializer,
+        {
+            RecordsCandid {
+                retention_limit: self.retention_limit,
+                retention_evicted_count: self.retention_evicted_count,
+                next_id: self.next_id,
+                records: &self.records,
+                archiving: &self.archiving,
+            }
+            .idl_serialize(serializer)
+        }
     }
 
     impl Default for Records {
@@ -194,10 +436,110 @@ pub mod basic {
                 retention_evicted_count: Default::default(),
                 next_id: Default::default(),
                 records: Default::default(),
+                archiving: Default::default(),
+                index: Default::default(),
+            }
+        }
+    }
+
+    /// 已归档的记录区间
+    #[derive(CandidType, Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+    pub struct RecordArchive {
+        /// 归档罐子
+        pub canister_id: CanisterId,
+        /// 包含的最小 id
+        pub start: RecordId,
+        /// 包含的最大 id
+        pub end: RecordId,
+    }
+
+    /// 归档设置与状态
+    #[derive(CandidType, Serialize, Deserialize, Debug, Clone, Default)]
+    pub struct RecordArchiving {
+        /// 归档罐子，设置后被淘汰的记录会等待归档
+        pub canister_id: Option<CanisterId>,
+        /// 每批最多推送的记录数量，0 表示使用默认值
+        pub batch_size: u64,
+        /// 等待推送的记录 正序
+        pub pending: Vec<Record>,
+        /// 已取出但还没有结果的记录数量，位于 pending 开头，同时只允许一批
+        #[serde(default)]
+        pub in_flight: u64,
+        /// 已归档的区间 正序
+        pub archives: Vec<RecordArchive>,
+    }
+
+    impl RecordArchiving {
+        const DEFAULT_BATCH_SIZE: u64 = 1000;
+
+        fn batch_size(&self) -> usize {
+            let batch_size = if self.batch_size == 0 {
+                Self::DEFAULT_BATCH_SIZE
+            } else {
+                self.batch_size
+            };
+            usize::try_from(batch_size).unwrap_or(usize::MAX)
+        }
+    }
+
+    /// 记录保存的位置，客户端据此获取完整的历史
+    #[derive(CandidType, Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+    pub struct RecordLocations {
+        /// 已归档的区间
+        pub archives: Vec<RecordArchive>,
+        /// 等待归档的 id 区间，通过本罐子的 [`Records::record_archive_pending`] 查询
+        pub pending: Option<(RecordId, RecordId)>,
+        /// 本罐子保留的 id 区间
+        pub local: Option<(RecordId, RecordId)>,
+    }
+
+    /// 归档罐子查询参数
+    #[derive(CandidType, Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+    pub struct RecordArchiveGetArg {
+        /// 起始 id
+        pub start: RecordId,
+        /// 最多返回的数量
+        pub length: u64,
+    }
+
+    /// 归档罐子保存的记录
+    #[derive(CandidType, Serialize, Deserialize, Debug, Clone, Default)]
+    pub struct ArchivedRecords {
+        /// 归档的记录 正序
+        pub records: Vec<Record>,
+    }
+
+    impl ArchivedRecords {
+        /// 追加记录，已经归档的 id 会被跳过，重复推送同一批记录是安全的
+        ///
+        /// 传入的记录 id 必须严格递增
+        pub fn append(&mut self, records: Vec<Record>) -> Result<(), String> {
+            if let Some(pair) = records.windows(2).find(|pair| pair[1].id <= pair[0].id) {
+                return Err(format!("record id {} is not increasing", pair[1].id.into_inner()));
             }
+            let last = self.records.last().map(|record| record.id);
+            self.records.extend(
+                records
+                    .into_iter()
+                    .filter(|record| last.is_none_or(|last| last < record.id)),
+            );
+            Ok(())
+        }
+
+        /// 从 start 开始查询记录
+        pub fn get(&self, arg: &RecordArchiveGetArg, max_length: u64) -> &[Record] {
+            get_by_id(&self.records, arg, max_length)
         }
     }
 
+    // 从按 id 正序的记录中取出一段
+    fn get_by_id<'a>(records: &'a [Record], arg: &RecordArchiveGetArg, max_length: u64) -> &'a [Record] {
+        let start = records.partition_point(|record| record.id < arg.start);
+        let length = usize::try_from(arg.length.min(max_length)).unwrap_or(usize::MAX);
+        let end = start.saturating_add(length).min(records.len());
+        &records[start..end]
+    }
+
     impl Records {
         fn push_at(
             &mut self,
@@ -209,26 +551,28 @@ pub mod basic {
             let id = self.next_id;
             self.next_id = self.next_id.next();
 
-            if self.retention_limit == 0 {
-                self.retention_evicted_count = self.retention_evicted_count.saturating_add(1);
-                return id;
-            }
-
-            let retention_limit = usize::try_from(self.retention_limit).unwrap_or(usize::MAX);
-            if retention_limit <= self.records.len() {
-                let remove_count = self.records.len() - retention_limit + 1;
-                self.records.drain(..remove_count);
-                self.retention_evicted_count = self.retention_evicted_count.saturating_add(remove_count as u64);
-            }
-
-            self.records.push(Record {
+            let record = Record {
                 id,
                 created,
                 caller,
                 topic,
                 content,
                 completion: None,
-            });
+            };
+            self.ensure_index();
+            let evicted = super::push_with_retention(&mut self.records, self.retention_limit, record);
+            self.retention_evicted_count = self.retention_evicted_count.saturating_add(evicted.len() as u64);
+            for record in evicted.iter().filter(|record| record.id != id) {
+                self.index.remove(record);
+            }
+            if let Some(record) = self.records.last()
+                && record.id == id
+            {
+                self.index.insert(record);
+            }
+            if self.archiving.canister_id.is_some() {
+                self.archiving.pending.extend(evicted);
+            }
 
             id
         }
@@ -238,6 +582,91 @@ pub mod basic {
                 item.completion = Some((completed_at, result));
             }
         }
+
+        fn index_ready(&self) -> bool {
+            self.index.size == self.records.len() as u64
+        }
+
+        fn ensure_index(&mut self) {
+            if !self.index_ready() {
+                self.record_reindex();
+            }
+        }
+
+        /// 重建检索索引
+        pub fn record_reindex(&mut self) {
+            self.index = RecordIndex::rebuild(&self.records);
+        }
+
+        /// 设置归档罐子，取消后已在等待的记录仍会保留
+        pub fn record_archive_set(&mut self, canister_id: Option<CanisterId>) {
+            self.archiving.canister_id = canister_id;
+        }
+
+        /// 取出一批等待归档的记录，推送完成后需要调用 [`Records::record_archive_done`]
+        ///
+        /// 上一批还没有结果时返回 None
+        pub fn record_archive_take(&mut self) -> Option<(CanisterId, Vec<Record>)> {
+            let canister_id = self.archiving.canister_id?;
+            if self.archiving.in_flight != 0 || self.archiving.pending.is_empty() {
+                return None;
+            }
+            let count = self.archiving.batch_size().min(self.archiving.pending.len());
+            self.archiving.in_flight = count as u64;
+            Some((canister_id, self.archiving.pending[..count].to_vec()))
+        }
+
+        /// 放弃正在推送的一批记录，例如罐子升级打断了推送，之后可以重新取出
+        ///
+        /// 归档罐子的 [`ArchivedRecords::append`] 会跳过已归档的记录，重复推送是安全的
+        pub fn record_archive_abort(&mut self) {
+            self.archiving.in_flight = 0;
+        }
+
+        /// 记录推送结果，成功则登记归档区间并移出等待列表，失败则留在等待列表
+        pub fn record_archive_done(
+            &mut self,
+            canister_id: CanisterId,
+            batch: Vec<Record>,
+            result: Result<(), CanisterCallError>,
+        ) {
+            let count = usize::try_from(self.archiving.in_flight).unwrap_or(usize::MAX);
+            let (Some(first), Some(last)) = (batch.first(), batch.last()) else {
+                return;
+            };
+            // 只接受当前正在推送的一批
+            if count != batch.len() || self.archiving.pending.first().map(|record| record.id) != Some(first.id) {
+                return;
+            }
+            self.archiving.in_flight = 0;
+            if result.is_err() {
+                return;
+            }
+            self.archiving.pending.drain(..count);
+            match self.archiving.archives.last_mut() {
+                Some(archive) if archive.canister_id == canister_id => archive.end = last.id,
+                _ => self.archiving.archives.push(RecordArchive {
+                    canister_id,
+                    start: first.id,
+                    end: last.id,
+                }),
+            }
+        }
+
+        /// 查询等待归档的记录，参数与归档罐子的查询相同
+        pub fn record_archive_pending(&self, arg: &RecordArchiveGetArg, max_length: u64) -> &[Record] {
+            get_by_id(&self.archiving.pending, arg, max_length)
+        }
+
+        /// 查询记录保存的位置
+        pub fn record_locations(&self) -> RecordLocations {
+            let range = |records: &[Record]| records.first().zip(records.last()).map(|(a, b)| (a.id, b.id));
+            RecordLocations {
+                archives: self.archiving.archives.clone(),
+                pending: range(&self.archiving.pending),
+                local: range(&self.records),
+            }
+        }
     }
 
     impl Recordable<Record, RecordTopic, RecordSearch> for Records {
@@ -264,10 +693,55 @@ pub mod basic {
                 return 0;
             }
 
+            self.ensure_index();
             let before = self.records.len();
-            self.records.retain(|record| !ids.contains(&record.id));
+            let index = &mut self.index;
+            self.records.retain(|record| {
+                let keep = !ids.contains(&record.id);
+                if !keep {
+                    index.remove(record);
+                }
+                keep
+            });
             u64::try_from(before - self.records.len()).unwrap_or(u64::MAX)
         }
+
+        // 分页查询 优先使用索引
+        fn record_find_by_page(
+            &self,
+            page: &QueryPage,
+            max_page_size: u32,
+            search: &Option<RecordSearch>,
+        ) -> Result<PageData<&Record>, QueryPageError> {
+            let Some(search) = search else {
+                return page.query_desc_by_list(&self.records, max_page_size);
+            };
+
+            // 记录按 id 有序，先按 id 范围截取
+            let (min, max) = search.id_range.unwrap_or_default();
+            let start = min.map_or(0, |min| self.records.partition_point(|record| record.id < min));
+            let end = max.map_or(self.records.len(), |max| {
+                self.records.partition_point(|record| record.id <= max)
+            });
+            let list = self.records.get(start..end.max(start)).unwrap_or_default();
+
+            let candidates = if self.index_ready() {
+                self.index.candidates(search)
+            } else {
+                None
+            };
+            let Some(candidates) = candidates else {
+                return page.query_desc_by_list_and_filter(list, max_page_size, |item| search.test(item));
+            };
+            let list: Vec<&Record> = candidates
+                .into_iter()
+                .filter_map(|id| list.binary_search_by_key(&id, |record| record.id).ok())
+                .filter_map(|index| list.get(index))
+                .filter(|record| search.test(record))
+                .collect();
+            let data = page.query_desc_by_list(&list, max_page_size)?;
+            Ok(page.from_data(data.total, data.data.into_iter().copied().collect()))
+        }
     }
 
     /// 记录检索
@@ -285,6 +759,9 @@ pub mod basic {
         pub topic: Option<HashSet<String>>,
         /// 内容过滤
         pub content: Option<String>,
+        /// 全文检索关键词，内容需要包含所有关键词
+        #[serde(default)]
+        pub keywords: Option<Vec<String>>,
     }
 
     impl RecordSearchArg {
@@ -301,6 +778,7 @@ pub mod basic {
                     .map(|topic| topic.iter().map(|t| f(t)).collect::<Result<HashSet<_>, _>>())
                     .transpose()?,
                 content: self.content,
+                keywords: self.keywords,
             })
         }
     }
@@ -313,10 +791,14 @@ pub mod basic {
         use ciborium::value::Value;
         use serde::Serialize;
 
-        use super::{RecordSearchArg, Records};
+        use super::{ArchivedRecords, RecordArchive, RecordArchiveGetArg, RecordSearch, RecordSearchArg, Records};
         use crate::{
-            functions::{record::RecordId, types::Recordable},
-            types::TimestampNanos,
+            canister::types::CanisterCallError,
+            functions::{
+                record::RecordId,
+                types::{Recordable, Searchable},
+            },
+            types::{QueryPage, TimestampNanos},
         };
 
         #[derive(Serialize)]
@@ -429,6 +911,155 @@ pub mod basic {
             assert_eq!(records.records.len(), 1);
         }
 
+        #[test]
+        fn archives_evicted_records_in_batches_and_restores_failed_batches() {
+            let archive_id = Principal::management_canister();
+            let mut records = Records {
+                retention_limit: 2,
+                ..Default::default()
+            };
+            push(&mut records, "before archiving", 0);
+            push(&mut records, "kept", 1);
+            records.record_archive_set(Some(archive_id));
+            records.archiving.batch_size = 2;
+            for i in 2..7 {
+                push(&mut records, "value", i);
+            }
+            assert_eq!(records.archiving.pending.len(), 5);
+
+            let (canister_id, batch) = records.record_archive_take().unwrap();
+            assert!(records.record_archive_take().is_none()); // 同时只允许一批
+            let error = CanisterCallError::new(canister_id, "append_records", "rejected");
+            records.record_archive_done(canister_id, batch, Err(error));
+            assert_eq!(records.archiving.pending[0].id, RecordId::from(0));
+            let arg = RecordArchiveGetArg {
+                start: RecordId::from(1),
+                length: 10,
+            };
+            let ids: Vec<u64> = records
+                .record_archive_pending(&arg, 3)
+                .iter()
+                .map(|r| r.id.into_inner())
+                .collect();
+            assert_eq!(ids, vec![1, 2, 3]);
+
+            // 结果未知时重复推送同一批
+            let mut archived = ArchivedRecords::default();
+            let (_, batch) = records.record_archive_take().unwrap();
+            archived.append(batch).unwrap();
+            records.record_archive_abort();
+
+            while let Some((canister_id, batch)) = records.record_archive_take() {
+                archived.append(batch.clone()).unwrap();
+                records.record_archive_done(canister_id, batch, Ok(()));
+            }
+            assert_eq!(archived.records.len(), 5);
+            assert!(
+                archived
+                    .append(vec![records.records[0].clone(), records.records[0].clone()])
+                    .is_err()
+            );
+
+            let locations = records.record_locations();
+            assert_eq!(
+                locations.archives,
+                vec![RecordArchive {
+                    canister_id: archive_id,
+                    start: RecordId::from(0),
+                    end: RecordId::from(4),
+                }]
+            );
+            assert_eq!(locations.pending, None);
+            assert_eq!(locations.local, Some((RecordId::from(5), RecordId::from(6))));
+
+            let arg = RecordArchiveGetArg {
+                start: RecordId::from(3),
+                length: 10,
+            };
+            let ids: Vec<u64> = archived.get(&arg, 10).iter().map(|r| r.id.into_inner()).collect();
+            assert_eq!(ids, vec![3, 4]);
+        }
+
+        #[test]
+        fn indexed_search_matches_linear_scan() {
+            let alice = Principal::from_slice(&[1]);
+            let mut records = Records {
+                retention_limit: 8,
+                ..Default::default()
+            };
+            for i in 0..12_i128 {
+                let caller = if i % 2 == 0 { alice } else { Principal::anonymous() };
+                let content = format!("Transfer {} tokens, memo-{}", i * 10, i % 3);
+                records.push_at(
+                    caller,
+                    (i % 3) as u8,
+                    content,
+                    TimestampNanos::from(i * 30 * 60 * 1_000_000_000),
+                );
+            }
+            records.record_delete(&HashSet::from([RecordId::from(6)]));
+            assert_eq!(records.index.size, 7);
+
+            let searches = [
+                RecordSearch {
+                    id_range: None,
+                    created_at_nanos_range: Some((Some(TimestampNanos::from(3 * 60 * 60 * 1_000_000_000)), None)),
+                    caller: Some(HashSet::from([alice])),
+                    topic: None,
+                    content: None,
+                    keywords: Some(vec!["MEMO".to_string()]),
+                },
+                RecordSearch {
+                    id_range: Some((Some(RecordId::from(5)), Some(RecordId::from(10)))),
+                    created_at_nanos_range: None,
+                    caller: None,
+                    topic: Some(HashSet::from([1, 2])),
+                    content: Some("tokens".to_string()),
+                    keywords: Some(vec!["memo 2".to_string()]),
+                },
+                RecordSearch {
+                    id_range: None,
+                    created_at_nanos_range: None,
+                    caller: None,
+                    topic: None,
+                    content: Some("ransfer".to_string()),
+                    keywords: None,
+                },
+            ];
+            let page = QueryPage { page: 1, size: 10 };
+            for search in searches {
+                let indexed: Vec<u64> = records
+                    .record_find_by_page(&page, 10, &Some(search.clone()))
+                    .unwrap()
+                    .data
+                    .iter()
+                    .map(|record| record.id.into_inner())
+                    .collect();
+                let scanned: Vec<u64> = records
+                    .records
+                    .iter()
+                    .rev()
+                    .filter(|record| search.test(record))
+                    .map(|record| record.id.into_inner())
+                    .collect();
+                assert!(!scanned.is_empty());
+                assert_eq!(indexed, scanned);
+            }
+
+            // 索引不参与序列化，恢复后回退为遍历，写入时重建
+            let mut cbor = Vec::new();
+            ciborium::ser::into_writer(&records, &mut cbor).unwrap();
+            let candid = candid::encode_one(&records).unwrap();
+            records = ciborium::de::from_reader(cbor.as_slice()).unwrap();
+            assert_eq!(candid::encode_one(&records).unwrap().len(), candid.len());
+            assert!(!<Records as candid::CandidType>::ty().to_string().contains("index"));
+            let decoded: Records = candid::decode_one(&candid).unwrap();
+            assert_eq!(decoded.records.len(), records.records.len());
+            assert!(!records.index_ready());
+            push(&mut records, "rebuild", 13 * 30 * 60 * 1_000_000_000);
+            assert!(records.index_ready());
+        }
+
         #[test]
         fn deserializes_legacy_aliases_and_serializes_current_names() {
             let legacy = LegacyRecords {
@@ -460,6 +1091,7 @@ pub mod basic {
             assert!(current_keys.contains(&"retention_evicted_count"));
             assert!(!current_keys.contains(&"max"));
             assert!(!current_keys.contains(&"removed"));
+            assert!(!current_keys.contains(&"index"));
 
             let Value::Map(entries) = current else { unreachable!() };
             let records = entries
@@ -496,3 +1128,241 @@ pub mod basic {
         }
     }
 }
+
+// ================== 自定义类型实现 ==================
+
+/// 记录内容、主题和执行结果都是自定义类型的实现
+pub mod typed {
+    use std::{collections::HashSet, hash::Hash};
+
+    use candid::CandidType;
+    use serde::{Deserialize, Serialize};
+
+    use crate::{
+        functions::types::{RecordId, Recordable, Searchable},
+        identity::CallerId,
+        types::TimestampNanos,
+    };
+
+    /// 每条记录
+    #[derive(CandidType, Serialize, Deserialize, Debug, Clone)]
+    pub struct TypedRecord<Topic, Content, Outcome> {
+        /// 记录 id
+        pub id: RecordId,
+        /// 创建时间戳 纳秒
+        pub created: TimestampNanos,
+        /// 调用人
+        pub caller: CallerId,
+        /// 记录主题
+        pub topic: Topic,
+        /// 记录内容
+        pub content: Content,
+        /// 完成时间与执行结果
+        pub completion: Option<(TimestampNanos, Outcome)>,
+    }
+
+    /// 记录检索
+    ///
+    /// 内容过滤可以是任意实现了 [`Searchable`] 的类型，包括 `Fn(&Content) -> bool`
+    #[derive(CandidType, Serialize, Deserialize, Debug, Clone)]
+    pub struct TypedRecordSearch<Topic: Eq + Hash, Filter> {
+        /// id 范围过滤，依次为包含下界和包含上界
+        pub id_range: Option<(Option<RecordId>, Option<RecordId>)>,
+        /// 创建时间纳秒范围过滤，依次为包含下界和包含上界
+        pub created_at_nanos_range: Option<(Option<TimestampNanos>, Option<TimestampNanos>)>,
+        /// 调用人过滤
+        pub caller: Option<HashSet<CallerId>>,
+        /// 主题过滤
+        pub topic: Option<HashSet<Topic>>,
+        /// 内容过滤
+        pub content: Option<Filter>,
+    }
+
+    impl<Topic: Eq + Hash, Filter> Default for TypedRecordSearch<Topic, Filter> {
+        fn default() -> Self {
+            Self {
+                id_range: None,
+                created_at_nanos_range: None,
+                caller: None,
+                topic: None,
+                content: None,
+            }
+        }
+    }
+
+    impl<Topic: Eq + Hash, Content, Outcome, Filter: Searchable<Content>>
+        Searchable<TypedRecord<Topic, Content, Outcome>> for TypedRecordSearch<Topic, Filter>
+    {
+        #[inline]
+        fn test(&self, record: &TypedRecord<Topic, Content, Outcome>) -> bool {
+            super::in_range(&record.id, &self.id_range)
+                && super::in_range(&record.created, &self.created_at_nanos_range)
+                && self
+                    .caller
+                    .as_ref()
+                    .is_none_or(|caller| caller.contains(&record.caller))
+                && self.topic.as_ref().is_none_or(|topic| topic.contains(&record.topic))
+                && self
+                    .content
+                    .as_ref()
+                    .is_none_or(|content| content.test(&record.content))
+        }
+    }
+
+    /// 持久化的记录对象
+    #[derive(CandidType, Serialize, Deserialize, Debug, Clone)]
+    pub struct TypedRecords<Topic, Content, Outcome> {
+        /// 最多保留的记录条数
+        pub retention_limit: u64,
+        /// 因保留上限被累计淘汰的记录数量
+        pub retention_evicted_count: u64,
+        /// 下一个未使用的 id
+        pub next_id: RecordId,
+        /// 当前保留的记录列表
+        pub records: Vec<TypedRecord<Topic, Content, Outcome>>,
+    }
+
+    impl<Topic, Content, Outcome> Default for TypedRecords<Topic, Content, Outcome> {
+        fn default() -> Self {
+            Self {
+                retention_limit: 1024 * 64,
+                retention_evicted_count: Default::default(),
+                next_id: Default::default(),
+                records: Default::default(),
+            }
+        }
+    }
+
+    impl<Topic, Content, Outcome> TypedRecords<Topic, Content, Outcome> {
+        pub(crate) fn push_at(
+            &mut self,
+            caller: CallerId,
+            topic: Topic,
+            content: Content,
+            created: TimestampNanos,
+        ) -> RecordId {
+            let id = self.next_id;
+            self.next_id = self.next_id.next();
+
+            let record = TypedRecord {
+                id,
+                created,
+                caller,
+                topic,
+                content,
+                completion: None,
+            };
+            let evicted = super::push_with_retention(&mut self.records, self.retention_limit, record);
+            self.retention_evicted_count = self.retention_evicted_count.saturating_add(evicted.len() as u64);
+
+            id
+        }
+
+        pub(crate) fn update_at(&mut self, record_id: RecordId, result: Outcome, completed_at: TimestampNanos) {
+            if let Some(item) = self.records.iter_mut().rev().find(|item| item.id == record_id) {
+                item.completion = Some((completed_at, result));
+            }
+        }
+    }
+
+    impl<Topic: Eq + Hash, Content, Outcome, Filter: Searchable<Content>>
+        Recordable<TypedRecord<Topic, Content, Outcome>, Topic, TypedRecordSearch<Topic, Filter>, Content, Outcome>
+        for TypedRecords<Topic, Content, Outcome>
+    {
+        // 查询
+
+        // 查询所有 正序
+        fn record_find_all(&self) -> &[TypedRecord<Topic, Content, Outcome>] {
+            &self.records
+        }
+
+        // 修改
+        fn record_push(&mut self, caller: CallerId, topic: Topic, content: Content) -> RecordId {
+            self.push_at(caller, topic, content, crate::times::now())
+        }
+
+        fn record_update(&mut self, record_id: RecordId, result: Outcome) {
+            self.update_at(record_id, result, crate::times::now());
+        }
+
+        fn record_delete(&mut self, ids: &HashSet<RecordId>) -> u64 {
+            if ids.is_empty() {
+                return 0;
+            }
+
+            let before = self.records.len();
+            self.records.retain(|record| !ids.contains(&record.id));
+            u64::try_from(before - self.records.len()).unwrap_or(u64::MAX)
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use candid::{CandidType, Principal};
+        use serde::{Deserialize, Serialize};
+
+        use super::{TypedRecordSearch, TypedRecords};
+        use crate::{
+            functions::types::{Recordable, Searchable},
+            types::{QueryPage, TimestampNanos},
+        };
+
+        #[derive(CandidType, Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+        enum Topic {
+            Transfer,
+            Upgrade,
+        }
+
+        #[derive(CandidType, Serialize, Deserialize, Debug, Clone)]
+        struct Transfer {
+            amount: u64,
+        }
+
+        #[derive(CandidType, Serialize, Deserialize, Debug, Clone)]
+        struct MinAmount(u64);
+
+        impl Searchable<Transfer> for MinAmount {
+            fn test(&self, content: &Transfer) -> bool {
+                self.0 <= content.amount
+            }
+        }
+
+        type Records = TypedRecords<Topic, Transfer, Result<(), String>>;
+
+        #[test]
+        fn filters_typed_payload_and_pages_with_recordable() {
+            let mut records = Records::default();
+            for (i, amount) in [5, 50, 500].into_iter().enumerate() {
+                let topic = if i == 0 { Topic::Upgrade } else { Topic::Transfer };
+                records.push_at(
+                    Principal::anonymous(),
+                    topic,
+                    Transfer { amount },
+                    TimestampNanos::from(i as i128),
+                );
+            }
+            records.update_at(1.into(), Err("rejected".to_string()), TimestampNanos::from(9));
+
+            let search = TypedRecordSearch {
+                topic: Some([Topic::Transfer].into()),
+                content: Some(MinAmount(100)),
+                ..Default::default()
+            };
+            let page = records
+                .record_find_by_page(&QueryPage { page: 1, size: 10 }, 10, &Some(search))
+                .unwrap();
+            assert_eq!(page.total, 1);
+            assert_eq!(page.data[0].content.amount, 500);
+
+            let search = TypedRecordSearch {
+                content: Some(|content: &Transfer| content.amount < 100),
+                ..Default::default()
+            };
+            let page = records
+                .record_find_by_page(&QueryPage { page: 1, size: 10 }, 10, &Some(search))
+                .unwrap();
+            assert_eq!(page.total, 2);
+            assert_eq!(page.data[0].completion.as_ref().unwrap().1, Err("rejected".to_string()));
+        }
+    }
+}
@@ -1,11 +1,22 @@
-use std::cell::Cell;
+use std::{
+    cell::{Cell, RefCell},
+    collections::HashSet,
+    rc::Rc,
+};
 
-use crate::types::DurationNanos;
+use candid::CandidType;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    functions::cron::CronSchedule,
+    types::{DurationNanos, TimestampNanos},
+};
 
 const MIN_SCHEDULE_INTERVAL_NANOS: u128 = 1_000_000_000;
 
 thread_local! {
     static SCHEDULE_TASK_RUNNING: Cell<bool> = const { Cell::new(false) };
+    static SCHEDULE_JOB_RUNNING: RefCell<HashSet<String>> = RefCell::default(); // 执行中的任务名称
 }
 
 /// 定时任务执行锁。
@@ -32,6 +43,28 @@ pub fn try_schedule_task_guard() -> Result<ScheduleTaskGuard, String> {
     })
 }
 
+/// 单个定时任务执行锁。
+///
+/// 锁离开作用域时会自动释放，不影响其他名称的定时任务。
+#[must_use = "dropping the guard immediately releases the schedule job lock"]
+pub struct ScheduleJobGuard(String);
+
+impl Drop for ScheduleJobGuard {
+    fn drop(&mut self) {
+        SCHEDULE_JOB_RUNNING.with_borrow_mut(|running| running.remove(&self.0));
+    }
+}
+
+/// 尝试取得某个定时任务的执行锁，防止同一任务的多次执行互相重叠。
+pub fn try_schedule_job_guard(name: &str) -> Result<ScheduleJobGuard, String> {
+    SCHEDULE_JOB_RUNNING.with_borrow_mut(|running| {
+        if !running.insert(name.to_string()) {
+            return Err(format!("Schedule job {name} is already running."));
+        }
+        Ok(ScheduleJobGuard(name.to_string()))
+    })
+}
+
 /// 验证定时任务间隔是否能够被 IC 定时器安全执行。
 ///
 /// 已启用的任务间隔不得少于一秒，也不得超过定时器的 `u64` 纳秒范围或导致当前 Canister 时间溢出。
@@ -84,6 +117,83 @@ pub fn schedule_stop(timer_id: Option<TimerId>) {
     }
 }
 
+/// 定时器句柄
+///
+/// 按 cron 表达式执行的任务每次触发后都会更换定时器，通过句柄始终可以停止当前的定时器
+#[derive(Debug, Clone, Default)]
+pub struct ScheduleTimer(Rc<Cell<Option<TimerId>>>);
+
+impl From<Option<TimerId>> for ScheduleTimer {
+    fn from(value: Option<TimerId>) -> Self {
+        Self(Rc::new(Cell::new(value)))
+    }
+}
+
+impl ScheduleTimer {
+    /// 停止定时器
+    #[inline]
+    pub fn stop(&self) {
+        schedule_stop(self.0.take())
+    }
+    /// 定时器是否还在运行
+    #[inline]
+    pub fn is_active(&self) -> bool {
+        self.0.get().is_some()
+    }
+}
+
+/// 定时任务触发方式
+#[derive(CandidType, Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub enum ScheduleTrigger {
+    /// 固定间隔
+    Interval(DurationNanos),
+    /// cron 表达式
+    Cron(CronSchedule),
+}
+
+impl ScheduleTrigger {
+    /// 验证触发方式是否能够被 IC 定时器安全执行
+    pub fn validate(&self) -> Result<(), String> {
+        match self {
+            ScheduleTrigger::Interval(interval) => validate_schedule(Some(*interval)).map(|_| ()),
+            ScheduleTrigger::Cron(_) => Ok(()),
+        }
+    }
+    /// 严格晚于指定时间的下一次触发时间
+    pub fn next_after(&self, now: TimestampNanos) -> Option<TimestampNanos> {
+        match self {
+            ScheduleTrigger::Interval(interval) => {
+                let interval = i128::try_from(interval.into_inner()).ok()?;
+                now.into_inner().checked_add(interval).map(TimestampNanos::from)
+            }
+            ScheduleTrigger::Cron(cron) => cron.next_after(now),
+        }
+    }
+}
+
+/// 多个命名定时任务
+pub trait ScheduleJobsable<Job> {
+    // 查询
+
+    /// 查询某个任务
+    fn schedule_job_find(&self, name: &str) -> Option<&Job>;
+    /// 查询所有任务 按名称排序
+    fn schedule_job_find_all(&self) -> Vec<(&String, &Job)>;
+
+    // 修改
+
+    /// 新增或修改任务触发方式，修改后需要重新启动定时器才能生效
+    fn schedule_job_insert(&mut self, name: String, trigger: ScheduleTrigger) -> Result<(), String>;
+    /// 移除任务
+    fn schedule_job_remove(&mut self, name: &str) -> Option<Job>;
+    /// 启用或禁用任务
+    fn schedule_job_enable(&mut self, name: &str, enabled: bool) -> Result<(), String>;
+    /// 记录任务开始执行，通过 start_schedule_jobs 启动的任务会自动调用
+    fn schedule_job_started(&mut self, name: &str);
+    /// 记录任务执行结果，通过 start_schedule_jobs 启动的任务会自动调用
+    fn schedule_job_finished(&mut self, name: &str, result: Result<(), String>);
+}
+
 /// 启动任务
 #[inline]
 pub fn schedule_start<F>(schedule: &Option<DurationNanos>, task: impl FnMut() -> F + 'static) -> Option<TimerId>
@@ -95,25 +205,78 @@ where
     })
 }
 
+/// 按 cron 表达式启动任务
+///
+/// 每次触发时都根据当前时间重新计算下一次触发时间，不会累积误差
+pub fn schedule_start_cron<F>(cron: &CronSchedule, task: impl FnMut() -> F + 'static) -> ScheduleTimer
+where
+    F: Future<Output = ()> + 'static,
+{
+    let timer = ScheduleTimer::default();
+    schedule_next_cron(cron.clone(), Rc::new(RefCell::new(task)), timer.clone());
+    timer
+}
+
+fn schedule_next_cron<T, F>(cron: CronSchedule, task: Rc<RefCell<T>>, timer: ScheduleTimer)
+where
+    T: FnMut() -> F + 'static,
+    F: Future<Output = ()> + 'static,
+{
+    let now = crate::times::now();
+    let Some(next) = cron.next_after(now) else {
+        timer.0.set(None); // 再也不会触发
+        return;
+    };
+    let delay = u64::try_from(next.into_inner() - now.into_inner()).unwrap_or(u64::MAX);
+    let handle = timer.clone();
+    let timer_id = ic_cdk_timers::set_timer(std::time::Duration::from_nanos(delay), async move {
+        let future = (task.borrow_mut())();
+        schedule_next_cron(cron, task, handle); // 先安排下一次，再执行本次
+        future.await;
+    });
+    timer.0.set(Some(timer_id));
+}
+
+/// 按触发方式启动任务
+pub fn schedule_start_trigger<F>(trigger: &ScheduleTrigger, task: impl FnMut() -> F + 'static) -> ScheduleTimer
+where
+    F: Future<Output = ()> + 'static,
+{
+    match trigger {
+        ScheduleTrigger::Interval(interval) => schedule_start(&Some(*interval), task).into(),
+        ScheduleTrigger::Cron(cron) => schedule_start_cron(cron, task),
+    }
+}
+
 // ================== 简单实现 ==================
 
 /// 定时任务简单实现
 pub mod basic {
+    use std::collections::HashMap;
+
     use candid::CandidType;
     use serde::{Deserialize, Serialize};
 
-    use crate::{functions::types::Schedulable, types::DurationNanos};
+    use crate::{
+        functions::types::{Schedulable, ScheduleJobsable, ScheduleTrigger},
+        types::{DurationNanos, TimestampNanos},
+    };
 
     #[cfg(feature = "schedule")]
     mod schedule {
-        use std::cell::RefCell;
+        use std::{cell::RefCell, collections::HashMap};
 
         use ic_cdk_timers::TimerId;
 
-        use crate::types::DurationNanos;
+        use super::ScheduleJobs;
+        use crate::{
+            functions::{schedule::ScheduleTimer, types::ScheduleJobsable},
+            types::DurationNanos,
+        };
 
         thread_local! {
             static SCHEDULE: RefCell<Option<TimerId>> = RefCell::default(); // 定时任务 id 记录
+            static SCHEDULE_JOBS: RefCell<HashMap<String, ScheduleTimer>> = RefCell::default(); // 命名定时任务记录
         }
 
         /// 停止定时任务
@@ -132,10 +295,208 @@ pub mod basic {
             let new_timer_id = crate::functions::schedule::schedule_start(schedule, task);
             SCHEDULE.with_borrow_mut(|timer_id| *timer_id = new_timer_id);
         }
+
+        /// 停止某个命名定时任务
+        #[inline]
+        pub fn stop_schedule_job(name: &str) {
+            if let Some(timer) = SCHEDULE_JOBS.with_borrow_mut(|timers| timers.remove(name)) {
+                timer.stop();
+            }
+        }
+
+        /// 停止所有命名定时任务
+        #[inline]
+        pub fn stop_schedule_jobs() {
+            for (_, timer) in SCHEDULE_JOBS.with_borrow_mut(std::mem::take) {
+                timer.stop();
+            }
+        }
+
+        // 执行前后通过 with_jobs 更新任务记录
+        async fn run_schedule_job<A, F>(name: &str, with_jobs: A, future: F)
+        where
+            A: Fn(&mut dyn FnMut(&mut ScheduleJobs)),
+            F: Future<Output = Result<(), String>>,
+        {
+            with_jobs(&mut |jobs| jobs.schedule_job_started(name));
+            let mut result = Some(future.await);
+            with_jobs(&mut |jobs| {
+                if let Some(result) = result.take() {
+                    jobs.schedule_job_finished(name, result);
+                }
+            });
+        }
+
+        /// 启动所有已启用的命名定时任务，升级后需要在 post_upgrade 中重新调用
+        ///
+        /// 所有任务共用一个执行函数，通过任务名称区分具体工作。
+        /// 同一任务上一次执行尚未结束时，本次触发会被跳过。
+        /// 每次执行前后会通过 `with_jobs` 更新任务的 last_run、next_run 和 last_error，
+        /// 例如 `|f| with_mut_state(|s| f(&mut s.jobs))`
+        pub fn start_schedule_jobs<A, F>(
+            jobs: &mut ScheduleJobs,
+            with_jobs: A,
+            task: impl Fn(String) -> F + Clone + 'static,
+        ) where
+            A: Fn(&mut dyn FnMut(&mut ScheduleJobs)) + Clone + 'static,
+            F: Future<Output = Result<(), String>> + 'static,
+        {
+            stop_schedule_jobs();
+            let now = crate::times::now();
+            for (name, job) in jobs.jobs.iter_mut() {
+                if !job.enabled {
+                    job.next_run = None;
+                    continue;
+                }
+                let timer = {
+                    let name = name.clone();
+                    let with_jobs = with_jobs.clone();
+                    let task = task.clone();
+                    crate::functions::schedule::schedule_start_trigger(&job.trigger, move || {
+                        let name = name.clone();
+                        let with_jobs = with_jobs.clone();
+                        let task = task.clone();
+                        async move {
+                            let Ok(_guard) = crate::functions::schedule::try_schedule_job_guard(&name) else {
+                                return; // 上一次还没执行完
+                            };
+                            run_schedule_job(&name, with_jobs, task(name.clone())).await;
+                        }
+                    })
+                };
+                SCHEDULE_JOBS.with_borrow_mut(|timers| timers.insert(name.clone(), timer));
+                job.next_run = job.trigger.next_after(now);
+            }
+        }
+
+        /// 启动单个命名定时任务，任务未启用则只停止已有的定时器
+        ///
+        /// 每次执行前后会通过 `with_jobs` 更新任务记录，同 [`start_schedule_jobs`]
+        pub fn start_schedule_job<A, F>(
+            jobs: &mut ScheduleJobs,
+            name: &str,
+            with_jobs: A,
+            task: impl FnMut() -> F + 'static,
+        ) where
+            A: Fn(&mut dyn FnMut(&mut ScheduleJobs)) + Clone + 'static,
+            F: Future<Output = Result<(), String>> + 'static,
+        {
+            stop_schedule_job(name);
+            let Some(job) = jobs.jobs.get_mut(name) else {
+                return;
+            };
+            if !job.enabled {
+                job.next_run = None;
+                return;
+            }
+            let job_name = name.to_string();
+            let mut task = task;
+            let timer = crate::functions::schedule::schedule_start_trigger(&job.trigger, move || {
+                let guard = crate::functions::schedule::try_schedule_job_guard(&job_name);
+                let future = guard.is_ok().then(&mut task);
+                let name = job_name.clone();
+                let with_jobs = with_jobs.clone();
+                async move {
+                    let _guard = guard;
+                    if let Some(future) = future {
+                        run_schedule_job(&name, with_jobs, future).await;
+                    }
+                }
+            });
+            SCHEDULE_JOBS.with_borrow_mut(|timers| timers.insert(name.to_string(), timer));
+            job.next_run = job.trigger.next_after(crate::times::now());
+        }
     }
     #[cfg(feature = "schedule")]
     pub use schedule::*;
 
+    /// 命名定时任务的状态
+    #[derive(CandidType, Serialize, Deserialize, Debug, Clone)]
+    pub struct ScheduleJob {
+        /// 触发方式
+        pub trigger: ScheduleTrigger,
+        /// 是否启用
+        pub enabled: bool,
+        /// 最近一次开始执行的时间
+        pub last_run: Option<TimestampNanos>,
+        /// 预计下一次执行的时间
+        pub next_run: Option<TimestampNanos>,
+        /// 最近一次失败的时间和错误
+        pub last_error: Option<(TimestampNanos, String)>,
+    }
+
+    /// 多个命名定时任务
+    #[derive(CandidType, Serialize, Deserialize, Debug, Clone, Default)]
+    pub struct ScheduleJobs {
+        /// 按名称记录的任务
+        pub jobs: HashMap<String, ScheduleJob>,
+    }
+
+    impl ScheduleJobs {
+        pub(super) fn started_at(&mut self, name: &str, now: TimestampNanos) {
+            if let Some(job) = self.jobs.get_mut(name) {
+                job.last_run = Some(now);
+                job.next_run = if job.enabled { job.trigger.next_after(now) } else { None };
+            }
+        }
+
+        pub(super) fn finished_at(&mut self, name: &str, result: Result<(), String>, now: TimestampNanos) {
+            if let Some(job) = self.jobs.get_mut(name)
+                && let Err(err) = result
+            {
+                job.last_error = Some((now, err));
+            }
+        }
+    }
+
+    impl ScheduleJobsable<ScheduleJob> for ScheduleJobs {
+        // 查询
+        fn schedule_job_find(&self, name: &str) -> Option<&ScheduleJob> {
+            self.jobs.get(name)
+        }
+        fn schedule_job_find_all(&self) -> Vec<(&String, &ScheduleJob)> {
+            let mut jobs: Vec<_> = self.jobs.iter().collect();
+            jobs.sort_by(|a, b| a.0.cmp(b.0));
+            jobs
+        }
+
+        // 修改
+        fn schedule_job_insert(&mut self, name: String, trigger: ScheduleTrigger) -> Result<(), String> {
+            trigger.validate()?;
+            self.jobs
+                .entry(name)
+                .and_modify(|job| job.trigger = trigger.clone())
+                .or_insert(ScheduleJob {
+                    trigger,
+                    enabled: true,
+                    last_run: None,
+                    next_run: None,
+                    last_error: None,
+                });
+            Ok(())
+        }
+        fn schedule_job_remove(&mut self, name: &str) -> Option<ScheduleJob> {
+            self.jobs.remove(name)
+        }
+        fn schedule_job_enable(&mut self, name: &str, enabled: bool) -> Result<(), String> {
+            let job = self
+                .jobs
+                .get_mut(name)
+                .ok_or_else(|| format!("Schedule job {name} is not found."))?;
+            job.enabled = enabled;
+            if !enabled {
+                job.next_run = None;
+            }
+            Ok(())
+        }
+        fn schedule_job_started(&mut self, name: &str) {
+            self.started_at(name, crate::times::now());
+        }
+        fn schedule_job_finished(&mut self, name: &str, result: Result<(), String>) {
+            self.finished_at(name, result, crate::times::now());
+        }
+    }
+
     /// 周期定时任务
     #[derive(CandidType, Serialize, Deserialize, Debug, Clone, Default)]
     pub struct Schedule(Option<DurationNanos>);
@@ -153,7 +514,9 @@ pub mod basic {
 }
 
 #[cfg(feature = "schedule")]
-pub use basic::{start_schedule, stop_schedule};
+pub use basic::{
+    start_schedule, start_schedule_job, start_schedule_jobs, stop_schedule, stop_schedule_job, stop_schedule_jobs,
+};
 
 #[cfg(test)]
 mod tests {
@@ -167,6 +530,56 @@ mod tests {
         assert!(try_schedule_task_guard().is_ok());
     }
 
+    #[test]
+    fn schedule_job_guard_only_blocks_the_same_job() {
+        let price = try_schedule_job_guard("price").expect("first price job should acquire the guard");
+        assert!(try_schedule_job_guard("price").is_err());
+        let cleanup = try_schedule_job_guard("cleanup");
+        assert!(cleanup.is_ok());
+        drop(price);
+        assert!(try_schedule_job_guard("price").is_ok());
+    }
+
+    #[test]
+    fn schedule_jobs_track_runs_and_errors() {
+        use crate::types::TimestampNanos;
+
+        let mut jobs = basic::ScheduleJobs::default();
+        jobs.jobs.insert(
+            "price".to_string(),
+            basic::ScheduleJob {
+                trigger: ScheduleTrigger::Interval(10_u128.into()),
+                enabled: true,
+                last_run: None,
+                next_run: None,
+                last_error: None,
+            },
+        );
+        assert!(jobs.schedule_job_enable("missing", false).is_err());
+
+        jobs.started_at("price", TimestampNanos::from(5));
+        jobs.finished_at("price", Err("ledger".to_string()), TimestampNanos::from(6));
+        let job = jobs.schedule_job_find("price").unwrap();
+        assert_eq!(job.last_run, Some(TimestampNanos::from(5)));
+        assert_eq!(job.next_run, Some(TimestampNanos::from(15)));
+        assert_eq!(job.last_error, Some((TimestampNanos::from(6), "ledger".to_string())));
+
+        jobs.schedule_job_enable("price", false).unwrap();
+        assert_eq!(jobs.schedule_job_find("price").unwrap().next_run, None);
+
+        let daily = ScheduleTrigger::Cron("@daily".parse().unwrap());
+        jobs.schedule_job_insert("cleanup".to_string(), daily).unwrap();
+        jobs.started_at("cleanup", TimestampNanos::from(5));
+        assert_eq!(
+            jobs.schedule_job_find("cleanup").unwrap().next_run,
+            Some(TimestampNanos::from(24 * 60 * 60 * 1_000_000_000))
+        );
+        assert!(
+            jobs.schedule_job_insert("fast".to_string(), ScheduleTrigger::Interval(1_u128.into()))
+                .is_err()
+        );
+    }
+
     #[test]
     fn schedule_interval_rejects_unsafe_values_before_reading_canister_time() {
         assert!(validate_schedule(Some(0_u128.into())).is_err());
@@ -0,0 +1,447 @@
+//! 自动充值 cycles
+
+/*
+
+定期查询受管理罐子的余额，低于阈值的罐子在预算内充值到目标余额，每次充值都会记录
+
+fn start_jobs() {
+    // 可以结合定时任务每小时检查一次
+    ic_canister_kit::functions::schedule::schedule_start(&Some(HOUR.into()), || async {
+        let targets = with_state(|s| s.topups.topup_find_all().iter().map(|t| (t.canister_id, t.source)).collect::<Vec<_>>());
+        let balances = ic_canister_kit::functions::topup::query_cycles_balances(&targets).await;
+        let plan = with_mut_state(|s| s.topups.topup_plan(balances));
+        for (top_up, result) in ic_canister_kit::functions::topup::deposit_top_ups(&plan).await {
+            with_mut_state(|s| s.topups.topup_done(top_up, result));
+        }
+    });
+}
+
+*/
+
+use candid::CandidType;
+use serde::{Deserialize, Serialize};
+
+use crate::{identity::CanisterId, types::TimestampNanos};
+
+/// 查询余额的方式
+#[derive(CandidType, Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CyclesBalanceSource {
+    /// 通过 canister_status 查询，需要是目标罐子的控制者
+    #[default]
+    CanisterStatus,
+    /// 调用目标罐子的 wallet_balance 接口
+    WalletBalance,
+}
+
+/// 计划的一次充值
+#[derive(CandidType, Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct CyclesTopUp {
+    /// 罐子 id
+    pub canister_id: CanisterId,
+    /// 查询到的余额
+    pub balance: u128,
+    /// 充值数量
+    pub amount: u128,
+    /// 计划时预算周期的开始时间，周期变化后失败的充值不再退回预算
+    pub period_start: TimestampNanos,
+}
+
+/// 充值失败的原因
+#[derive(CandidType, Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub enum CyclesTopUpError {
+    /// 确定没有充值，cycles 已经退回
+    Rejected(String),
+    /// 结果未知，cycles 可能已经充值
+    Unknown(String),
+}
+
+impl std::fmt::Display for CyclesTopUpError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CyclesTopUpError::Rejected(message) => write!(f, "rejected: {message}"),
+            CyclesTopUpError::Unknown(message) => write!(f, "unknown: {message}"),
+        }
+    }
+}
+
+impl std::error::Error for CyclesTopUpError {}
+
+impl From<ic_cdk::call::Error> for CyclesTopUpError {
+    fn from(err: ic_cdk::call::Error) -> Self {
+        use ic_cdk::call::{Error, RejectCode};
+        match &err {
+            Error::CallRejected(rejected) if rejected.reject_code() == Ok(RejectCode::SysUnknown) => {
+                CyclesTopUpError::Unknown(err.to_string())
+            }
+            Error::InsufficientLiquidCycleBalance(_) | Error::CallPerformFailed(_) | Error::CallRejected(_) => {
+                CyclesTopUpError::Rejected(err.to_string())
+            }
+            Error::CandidDecodeFailed(_) => CyclesTopUpError::Unknown(err.to_string()), // 调用已经成功
+        }
+    }
+}
+
+// ================== 功能 ==================
+
+/// 自动充值 cycles
+pub trait CyclesTopUpable<Target> {
+    // 查询
+
+    /// 查询所有受管理的罐子
+    fn topup_find_all(&self) -> Vec<&Target>;
+
+    // 修改
+
+    /// 添加或更新受管理的罐子，余额低于 threshold 时充值到 target
+    fn topup_insert(
+        &mut self,
+        canister_id: CanisterId,
+        threshold: u128,
+        target: u128,
+        source: CyclesBalanceSource,
+    ) -> Result<(), String>;
+    /// 移除受管理的罐子
+    fn topup_remove(&mut self, canister_id: &CanisterId) -> Option<Target>;
+
+    /// 根据查询到的余额计算需要充值的罐子，余额最低的优先，充值数量会预先计入预算
+    fn topup_plan(&mut self, balances: Vec<(CanisterId, Result<u128, String>)>) -> Vec<CyclesTopUp>;
+    /// 记录充值结果，确定没有充值且仍在计划时的预算周期内才会退回预算
+    fn topup_done(&mut self, top_up: CyclesTopUp, result: Result<(), CyclesTopUpError>);
+}
+
+/// 并发查询罐子余额
+pub async fn query_cycles_balances(
+    targets: &[(CanisterId, CyclesBalanceSource)],
+) -> Vec<(CanisterId, Result<u128, String>)> {
+    let futures = targets.iter().map(|(canister_id, source)| async move {
+        let balance = match source {
+            CyclesBalanceSource::CanisterStatus => crate::canister::status::canister_status(*canister_id)
+                .await
+                .map(|status| status.cycles),
+            CyclesBalanceSource::WalletBalance => crate::canister::cycles::call_wallet_balance(*canister_id).await,
+        };
+        let balance = balance
+            .map(|balance| u128::try_from(balance.0).unwrap_or(u128::MAX))
+            .map_err(|err| err.to_string());
+        (*canister_id, balance)
+    });
+    crate::canister::join_all(futures.collect()).await
+}
+
+/// 依次执行充值，返回每次充值的结果
+pub async fn deposit_top_ups(plan: &[CyclesTopUp]) -> Vec<(CyclesTopUp, Result<(), CyclesTopUpError>)> {
+    let mut results = Vec::with_capacity(plan.len());
+    for top_up in plan {
+        let arg = ic_management_canister_types::DepositCyclesArgs {
+            canister_id: top_up.canister_id,
+        };
+        let result = ic_cdk_management_canister::deposit_cycles(&arg, top_up.amount).await;
+        results.push((top_up.clone(), result.map_err(CyclesTopUpError::from)));
+    }
+    results
+}
+
+// ================== 简单实现 ==================
+
+/// 自动充值简单实现
+pub mod basic {
+    use std::collections::BTreeMap;
+
+    use candid::CandidType;
+    use serde::{Deserialize, Serialize};
+
+    use super::{CyclesBalanceSource, CyclesTopUp, CyclesTopUpError};
+    use crate::{
+        functions::types::{CyclesTopUpable, TypedRecords},
+        identity::{CallerId, CanisterId},
+        types::{DurationNanos, TimestampNanos},
+    };
+
+    /// 受管理的罐子
+    #[derive(CandidType, Serialize, Deserialize, Debug, Clone)]
+    pub struct CyclesTopUpTarget {
+        /// 罐子 id
+        pub canister_id: CanisterId,
+        /// 余额低于该值时充值
+        pub threshold: u128,
+        /// 充值后的目标余额
+        pub target: u128,
+        /// 查询余额的方式
+        pub source: CyclesBalanceSource,
+        /// 最近一次查询到的余额
+        pub last_balance: Option<(TimestampNanos, u128)>,
+        /// 最近一次查询或充值的错误
+        pub last_error: Option<(TimestampNanos, String)>,
+    }
+
+    /// 每个周期的充值预算
+    #[derive(CandidType, Serialize, Deserialize, Debug, Clone)]
+    pub struct CyclesTopUpBudget {
+        /// 周期长度
+        pub period: DurationNanos,
+        /// 每个周期最多充值的数量
+        pub amount: u128,
+        /// 当前周期开始时间
+        pub period_start: TimestampNanos,
+        /// 当前周期已经使用的数量
+        pub spent: u128,
+    }
+
+    impl Default for CyclesTopUpBudget {
+        fn default() -> Self {
+            Self {
+                period: (24 * 60 * 60 * 1_000_000_000_u128).into(), // 1 天
+                amount: 10_000_000_000_000,                         // 10T
+                period_start: TimestampNanos::from(0),
+                spent: 0,
+            }
+        }
+    }
+
+    impl CyclesTopUpBudget {
+        fn remaining_at(&mut self, now: TimestampNanos) -> u128 {
+            let period = i128::try_from(self.period.into_inner()).unwrap_or(i128::MAX);
+            if self.period_start.into_inner().saturating_add(period) <= now.into_inner() {
+                self.period_start = now;
+                self.spent = 0;
+            }
+            self.amount.saturating_sub(self.spent)
+        }
+    }
+
+    /// 充值记录
+    #[derive(CandidType, Serialize, Deserialize, Debug, Clone)]
+    pub struct CyclesTopUpContent {
+        /// 查询到的余额
+        pub balance: u128,
+        /// 充值数量
+        pub amount: u128,
+    }
+
+    /// 充值记录，主题为被充值的罐子
+    pub type CyclesTopUpRecords = TypedRecords<CanisterId, CyclesTopUpContent, Result<(), String>>;
+
+    /// 持久化的自动充值
+    #[derive(CandidType, Serialize, Deserialize, Debug, Clone, Default)]
+    pub struct CyclesTopUps {
+        /// 受管理的罐子
+        pub targets: BTreeMap<CanisterId, CyclesTopUpTarget>,
+        /// 充值预算
+        pub budget: CyclesTopUpBudget,
+        /// 充值记录
+        pub records: CyclesTopUpRecords,
+    }
+
+    impl CyclesTopUps {
+        fn plan_at(
+            &mut self,
+            balances: Vec<(CanisterId, Result<u128, String>)>,
+            now: TimestampNanos,
+        ) -> Vec<CyclesTopUp> {
+            let mut needs = Vec::new();
+            for (canister_id, balance) in balances {
+                let Some(target) = self.targets.get_mut(&canister_id) else {
+                    continue;
+                };
+                match balance {
+                    Ok(balance) => {
+                        target.last_balance = Some((now, balance));
+                        if balance < target.threshold {
+                            needs.push((balance, canister_id, target.target.saturating_sub(balance)));
+                        }
+                    }
+                    Err(message) => target.last_error = Some((now, message)),
+                }
+            }
+            needs.sort();
+
+            let mut remaining = self.budget.remaining_at(now);
+            let mut plan = Vec::new();
+            for (balance, canister_id, need) in needs {
+                let amount = need.min(remaining);
+                if amount == 0 {
+                    continue;
+                }
+                remaining -= amount;
+                self.budget.spent = self.budget.spent.saturating_add(amount);
+                plan.push(CyclesTopUp {
+                    canister_id,
+                    balance,
+                    amount,
+                    period_start: self.budget.period_start,
+                });
+            }
+            plan
+        }
+
+        fn done_at(
+            &mut self,
+            caller: CallerId,
+            top_up: CyclesTopUp,
+            result: Result<(), CyclesTopUpError>,
+            now: TimestampNanos,
+        ) {
+            if let Err(err) = &result {
+                // 结果未知时 cycles 可能已经充值，周期变化后退回会计入新的周期，都不退回
+                if matches!(err, CyclesTopUpError::Rejected(_)) && top_up.period_start == self.budget.period_start {
+                    self.budget.spent = self.budget.spent.saturating_sub(top_up.amount);
+                }
+                if let Some(target) = self.targets.get_mut(&top_up.canister_id) {
+                    target.last_error = Some((now, err.to_string()));
+                }
+            }
+            let result = result.map_err(|err| err.to_string());
+            let content = CyclesTopUpContent {
+                balance: top_up.balance,
+                amount: top_up.amount,
+            };
+            let id = self.records.push_at(caller, top_up.canister_id, content, now);
+            self.records.update_at(id, result, now);
+        }
+    }
+
+    impl CyclesTopUpable<CyclesTopUpTarget> for CyclesTopUps {
+        // 查询
+        fn topup_find_all(&self) -> Vec<&CyclesTopUpTarget> {
+            self.targets.values().collect()
+        }
+
+        // 修改
+        fn topup_insert(
+            &mut self,
+            canister_id: CanisterId,
+            threshold: u128,
+            target: u128,
+            source: CyclesBalanceSource,
+        ) -> Result<(), String> {
+            if target <= threshold {
+                return Err(format!("target({target}) must be greater than threshold({threshold})"));
+            }
+            let item = self.targets.entry(canister_id).or_insert_with(|| CyclesTopUpTarget {
+                canister_id,
+                threshold,
+                target,
+                source,
+                last_balance: None,
+                last_error: None,
+            });
+            item.threshold = threshold;
+            item.target = target;
+            item.source = source;
+            Ok(())
+        }
+        fn topup_remove(&mut self, canister_id: &CanisterId) -> Option<CyclesTopUpTarget> {
+            self.targets.remove(canister_id)
+        }
+
+        fn topup_plan(&mut self, balances: Vec<(CanisterId, Result<u128, String>)>) -> Vec<CyclesTopUp> {
+            self.plan_at(balances, crate::times::now())
+        }
+        fn topup_done(&mut self, top_up: CyclesTopUp, result: Result<(), CyclesTopUpError>) {
+            self.done_at(crate::identity::self_canister_id(), top_up, result, crate::times::now());
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use candid::Principal;
+
+        use super::{CyclesTopUp, CyclesTopUps};
+        use crate::{
+            functions::{
+                topup::{CyclesBalanceSource, CyclesTopUpError},
+                types::{CyclesTopUpable, Recordable, TypedRecordSearch},
+            },
+            types::{QueryPage, TimestampNanos},
+        };
+
+        #[test]
+        fn tops_up_lowest_balances_first_within_budget() {
+            let (a, b, c) = (
+                Principal::from_slice(&[1]),
+                Principal::from_slice(&[2]),
+                Principal::from_slice(&[3]),
+            );
+            let mut topups = CyclesTopUps::default();
+            topups.budget.amount = 150;
+            topups.budget.period = 100_u128.into();
+            for canister_id in [a, b, c] {
+                topups
+                    .topup_insert(canister_id, 100, 200, CyclesBalanceSource::CanisterStatus)
+                    .unwrap();
+            }
+            assert!(
+                topups
+                    .topup_insert(a, 100, 100, CyclesBalanceSource::WalletBalance)
+                    .is_err()
+            );
+
+            let now = TimestampNanos::from(10);
+            let plan = topups.plan_at(vec![(a, Ok(90)), (b, Ok(20)), (c, Err("stopped".to_string()))], now);
+            assert_eq!(
+                plan.iter()
+                    .map(|top_up| (top_up.canister_id, top_up.amount))
+                    .collect::<Vec<_>>(),
+                vec![(b, 150)]
+            );
+            assert_eq!(topups.targets[&c].last_error.as_ref().unwrap().1, "stopped");
+
+            // 确定失败的充值退回预算
+            topups.done_at(
+                a,
+                plan[0].clone(),
+                Err(CyclesTopUpError::Rejected("rejected".to_string())),
+                now,
+            );
+            let plan = topups.plan_at(vec![(a, Ok(90))], now);
+            assert_eq!(plan[0].amount, 110);
+            topups.done_at(a, plan[0].clone(), Ok(()), now);
+            assert_eq!(topups.budget.spent, 110);
+
+            // 结果未知的充值不退回预算
+            let plan = topups.plan_at(vec![(b, Ok(90))], now);
+            assert_eq!(plan[0].amount, 40);
+            topups.done_at(
+                a,
+                plan[0].clone(),
+                Err(CyclesTopUpError::Unknown("timeout".to_string())),
+                now,
+            );
+            assert_eq!(topups.budget.spent, 150);
+
+            // 新的周期重置预算，上个周期计划的充值失败不会退回到新的周期
+            assert!(topups.plan_at(vec![(b, Ok(0))], now).is_empty());
+            let next = TimestampNanos::from(110);
+            let plan = topups.plan_at(vec![(b, Ok(90))], next);
+            assert_eq!(plan[0].amount, 110);
+            let stale = CyclesTopUp {
+                period_start: now,
+                ..plan[0].clone()
+            };
+            topups.done_at(a, stale, Err(CyclesTopUpError::Rejected("rejected".to_string())), next);
+            assert_eq!(topups.budget.spent, 110);
+            topups.done_at(
+                a,
+                plan[0].clone(),
+                Err(CyclesTopUpError::Rejected("rejected".to_string())),
+                next,
+            );
+            assert_eq!(topups.budget.spent, 0);
+
+            let search = TypedRecordSearch {
+                topic: Some([b].into()),
+                content: Some(|_: &_| true),
+                ..Default::default()
+            };
+            let page = topups
+                .records
+                .record_find_by_page(&QueryPage { page: 1, size: 10 }, 10, &Some(search))
+                .unwrap();
+            assert_eq!(page.total, 4);
+            assert_eq!(
+                page.data[0].completion.as_ref().unwrap().1,
+                Err("rejected: rejected".to_string())
+            );
+        }
+    }
+}
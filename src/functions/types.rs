@@ -4,19 +4,71 @@ pub use super::upgrade::{StateUpgrade, Upgrade};
 
 pub use super::pausable::{
     Pausable, Reasonable,
-    basic::{Pause, PauseReason},
+    basic::{Pause, PauseAction, PauseCategory, PauseCyclesThreshold, PauseEvent, PauseReason, PauseWindow},
 };
 
-pub use super::schedule::{Schedulable, TimerId, basic::Schedule};
+pub use super::cron::CronSchedule;
+
+pub use super::schedule::{
+    Schedulable, ScheduleJobsable, ScheduleTimer, ScheduleTrigger, TimerId,
+    basic::{Schedule, ScheduleJob, ScheduleJobs},
+};
+
+pub use super::queue::{
+    Queueable,
+    basic::{QueueTask, RetryPolicy, TaskQueue},
+};
+
+pub use super::delay::{
+    Delayable,
+    basic::{DelayedTask, DelayedTasks},
+};
+
+pub use super::fleet::{
+    Fleetable,
+    basic::{Fleet, FleetChild, FleetChildStatus, FleetRollout},
+};
+
+pub use super::wasm::{
+    WasmRegistrable, WasmVersion,
+    basic::{WasmRelease, WasmReleaseInfo, WasmReleases, WasmUpload},
+};
+
+pub use super::lifecycle::{
+    CanisterLifecyclable, CanisterLifecycleState,
+    basic::{ManagedCanister, ManagedCanisters},
+};
+
+pub use super::topup::{
+    CyclesBalanceSource, CyclesTopUp, CyclesTopUpError, CyclesTopUpable,
+    basic::{CyclesTopUpBudget, CyclesTopUpContent, CyclesTopUpRecords, CyclesTopUpTarget, CyclesTopUps},
+};
+
+pub use super::burn::{
+    CyclesBurnTrackable, CyclesConsumedByCategory,
+    basic::{CyclesBurn, CyclesBurnReport, CyclesSample},
+};
 
 pub use super::permission::{
     Permissable, PermissionUpdatedArg, PermissionUpdatedError,
-    basic::{Permission, Permissions},
+    basic::{Permission, Permissions, PermissionsDocument},
+};
+
+pub use super::limit::{
+    RateLimitable,
+    basic::{
+        AnonymousRateLimit, RateLimitBucket, RateLimitConfig, RateLimitError, RateLimitRule, RateLimitState,
+        RateLimiter,
+    },
 };
 
 pub use super::record::{
     RecordId, Recordable, Searchable,
-    basic::{Record, RecordSearch, RecordSearchArg, RecordTopic, Records},
+    basic::{
+        ArchivedRecords, Record, RecordArchive, RecordArchiveGetArg, RecordArchiving, RecordIndex, RecordLocations,
+        RecordSearch, RecordSearchArg, RecordTopic, Records,
+    },
+    typed::{TypedRecord, TypedRecordSearch, TypedRecords},
 };
 
 pub use super::stable::StableHeap;
@@ -0,0 +1,478 @@
+//! Wasm 模块仓库
+
+/*
+
+管理员分块上传 Wasm，校验 hash 后登记版本，升级子罐子时引用仓库中的版本
+
+#[ic_cdk::update]
+fn wasm_upload_start(version: String, hash: CanisterCodeHash, notes: String) -> Result<(), String> {
+    with_mut_state(|s| s.wasm.wasm_upload_start(version.parse()?, hash, notes))
+}
+
+#[ic_cdk::update]
+fn wasm_upload_chunk(version: String, chunk: Vec<u8>) -> Result<u64, String> {
+    with_mut_state(|s| s.wasm.wasm_upload_chunk(&version.parse()?, chunk))
+}
+
+#[ic_cdk::update]
+fn wasm_upload_finish(version: String) -> Result<(), String> {
+    with_mut_state(|s| s.wasm.wasm_upload_finish(&version.parse()?))
+}
+
+*/
+
+use std::{fmt::Display, str::FromStr};
+
+use candid::{
+    CandidType,
+    types::{Serializer, Type},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::canister::codes::CanisterCodeHash;
+
+/// 语义化版本号 `MAJOR.MINOR.PATCH[-PRE][+BUILD]`，构建信息会被忽略
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct WasmVersion {
+    /// 主版本
+    pub major: u64,
+    /// 次版本
+    pub minor: u64,
+    /// 修订版本
+    pub patch: u64,
+    /// 预发布标记
+    pub pre: Option<String>,
+}
+
+impl CandidType for WasmVersion {
+    fn _ty() -> Type {
+        String::ty()
+    }
+    fn idl_serialize<S>(&self, serializer: S) -> Result<(), S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_text(&self.to_string())
+    }
+}
+
+impl FromStr for WasmVersion {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("invalid version: {value}");
+        // 构建信息不参与比较，直接忽略
+        let value_without_build = match value.split_once('+') {
+            Some((version, build)) if is_identifiers(build, false) => version,
+            Some(_) => return Err(invalid()),
+            None => value,
+        };
+        let (core, pre) = match value_without_build.split_once('-') {
+            Some((core, pre)) if is_identifiers(pre, true) => (core, Some(pre.to_string())),
+            Some(_) => return Err(invalid()),
+            None => (value_without_build, None),
+        };
+        let numbers = core
+            .split('.')
+            .map(|n| n.parse::<u64>().map_err(|_| invalid()))
+            .collect::<Result<Vec<_>, _>>()?;
+        let [major, minor, patch] = numbers[..] else {
+            return Err(invalid());
+        };
+        Ok(Self {
+            major,
+            minor,
+            patch,
+            pre,
+        })
+    }
+}
+
+// 以点分隔的标识符，只能包含字母数字和连字符
+fn is_identifiers(value: &str, pre: bool) -> bool {
+    value.split('.').all(|identifier| {
+        !identifier.is_empty()
+            && identifier.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+            // 预发布的数字标识符不能有前导 0
+            && !(pre && 1 < identifier.len() && identifier.starts_with('0') && identifier.chars().all(|c| c.is_ascii_digit()))
+    })
+}
+
+// 预发布标识符逐个比较，数字按数值比较并低于非数字，前缀相同时较短的更低
+fn compare_pre(a: &str, b: &str) -> std::cmp::Ordering {
+    let mut a = a.split('.');
+    let mut b = b.split('.');
+    loop {
+        let ordering = match (a.next(), b.next()) {
+            (None, None) => return std::cmp::Ordering::Equal,
+            (None, Some(_)) => return std::cmp::Ordering::Less,
+            (Some(_), None) => return std::cmp::Ordering::Greater,
+            (Some(a), Some(b)) => match (a.parse::<u64>(), b.parse::<u64>()) {
+                (Ok(a), Ok(b)) => a.cmp(&b),
+                (Ok(_), Err(_)) => std::cmp::Ordering::Less,
+                (Err(_), Ok(_)) => std::cmp::Ordering::Greater,
+                (Err(_), Err(_)) => a.cmp(b),
+            },
+        };
+        if ordering != std::cmp::Ordering::Equal {
+            return ordering;
+        }
+    }
+}
+
+impl TryFrom<String> for WasmVersion {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl From<WasmVersion> for String {
+    fn from(value: WasmVersion) -> Self {
+        value.to_string()
+    }
+}
+
+impl Display for WasmVersion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)?;
+        if let Some(pre) = &self.pre {
+            write!(f, "-{pre}")?;
+        }
+        Ok(())
+    }
+}
+
+impl Ord for WasmVersion {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        (self.major, self.minor, self.patch)
+            .cmp(&(other.major, other.minor, other.patch))
+            .then_with(|| match (&self.pre, &other.pre) {
+                // 预发布版本低于正式版本
+                (None, None) => std::cmp::Ordering::Equal,
+                (None, Some(_)) => std::cmp::Ordering::Greater,
+                (Some(_), None) => std::cmp::Ordering::Less,
+                (Some(a), Some(b)) => compare_pre(a, b),
+            })
+    }
+}
+
+impl PartialOrd for WasmVersion {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+// ================== 功能 ==================
+
+/// Wasm 模块仓库
+pub trait WasmRegistrable<Release> {
+    // 查询
+
+    /// 查询所有版本 按版本升序
+    fn wasm_find_all(&self) -> Vec<&Release>;
+    /// 查询某个版本
+    fn wasm_find(&self, version: &WasmVersion) -> Option<&Release>;
+    /// 查询最新的正式版本
+    fn wasm_find_latest(&self) -> Option<&Release>;
+
+    // 修改
+
+    /// 开始上传，需要提供期望的 SHA-256 hash
+    fn wasm_upload_start(
+        &mut self,
+        version: WasmVersion,
+        expected_hash: CanisterCodeHash,
+        release_notes: String,
+    ) -> Result<(), String>;
+    /// 上传一个分块，返回已上传的字节数
+    fn wasm_upload_chunk(&mut self, version: &WasmVersion, chunk: Vec<u8>) -> Result<u64, String>;
+    /// 完成上传，hash 校验通过后登记版本
+    fn wasm_upload_finish(&mut self, version: &WasmVersion) -> Result<(), String>;
+    /// 放弃上传
+    fn wasm_upload_cancel(&mut self, version: &WasmVersion) -> bool;
+    /// 删除某个版本，`in_use` 中的 hash 对应的版本不能删除，例如正在升级的目标版本
+    fn wasm_delete(&mut self, version: &WasmVersion, in_use: &[&CanisterCodeHash]) -> Result<Release, String>;
+}
+
+// ================== 简单实现 ==================
+
+/// Wasm 模块仓库简单实现
+pub mod basic {
+    use std::collections::BTreeMap;
+
+    use candid::CandidType;
+    use serde::{Deserialize, Serialize};
+
+    use super::WasmVersion;
+    use crate::{
+        canister::codes::{CanisterCodeHash, CanisterCodeWasm},
+        functions::types::WasmRegistrable,
+        types::TimestampNanos,
+    };
+
+    /// 已登记的版本
+    #[derive(CandidType, Serialize, Deserialize, Debug, Clone)]
+    pub struct WasmRelease {
+        /// 版本
+        pub version: WasmVersion,
+        /// SHA-256 hash
+        #[serde(with = "serde_bytes")]
+        pub wasm_module_hash: CanisterCodeHash,
+        /// 发布说明
+        pub release_notes: String,
+        /// 登记时间
+        pub created: TimestampNanos,
+        /// 模块内容
+        #[serde(with = "serde_bytes")]
+        pub wasm_module: CanisterCodeWasm,
+    }
+
+    /// 版本信息，不包含模块内容
+    #[derive(CandidType, Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+    pub struct WasmReleaseInfo {
+        /// 版本
+        pub version: WasmVersion,
+        /// SHA-256 hash
+        #[serde(with = "serde_bytes")]
+        pub wasm_module_hash: CanisterCodeHash,
+        /// 发布说明
+        pub release_notes: String,
+        /// 登记时间
+        pub created: TimestampNanos,
+        /// 模块大小
+        pub size: u64,
+    }
+
+    impl WasmRelease {
+        /// 版本信息
+        pub fn info(&self) -> WasmReleaseInfo {
+            WasmReleaseInfo {
+                version: self.version.clone(),
+                wasm_module_hash: self.wasm_module_hash.clone(),
+                release_notes: self.release_notes.clone(),
+                created: self.created,
+                size: self.wasm_module.len() as u64,
+            }
+        }
+    }
+
+    /// 上传中的模块
+    #[derive(CandidType, Serialize, Deserialize, Debug, Clone)]
+    pub struct WasmUpload {
+        /// 期望的 SHA-256 hash
+        #[serde(with = "serde_bytes")]
+        pub expected_hash: CanisterCodeHash,
+        /// 发布说明
+        pub release_notes: String,
+        /// 开始时间
+        pub created: TimestampNanos,
+        /// 已上传的内容
+        #[serde(with = "serde_bytes")]
+        pub wasm_module: CanisterCodeWasm,
+    }
+
+    /// 持久化的 Wasm 模块仓库
+    #[derive(CandidType, Serialize, Deserialize, Debug, Clone)]
+    pub struct WasmReleases {
+        /// 单个模块最大字节数
+        pub max_module_size: u64,
+        /// 已登记的版本
+        pub releases: BTreeMap<WasmVersion, WasmRelease>,
+        /// 上传中的模块
+        pub uploads: BTreeMap<WasmVersion, WasmUpload>,
+    }
+
+    impl Default for WasmReleases {
+        fn default() -> Self {
+            Self {
+                max_module_size: 100 * 1024 * 1024, // 管理罐子允许的最大 Wasm 模块
+                releases: Default::default(),
+                uploads: Default::default(),
+            }
+        }
+    }
+
+    impl WasmReleases {
+        fn upload_start_at(
+            &mut self,
+            version: WasmVersion,
+            expected_hash: CanisterCodeHash,
+            release_notes: String,
+            now: TimestampNanos,
+        ) -> Result<(), String> {
+            if self.releases.contains_key(&version) {
+                return Err(format!("version {version} already exists"));
+            }
+            if expected_hash.len() != 32 {
+                return Err("expected hash must be 32 bytes".to_string());
+            }
+            self.uploads.insert(
+                version,
+                WasmUpload {
+                    expected_hash,
+                    release_notes,
+                    created: now,
+                    wasm_module: Vec::new(),
+                },
+            );
+            Ok(())
+        }
+
+        fn upload_finish_at(&mut self, version: &WasmVersion, now: TimestampNanos) -> Result<(), String> {
+            let upload = self
+                .uploads
+                .get(version)
+                .ok_or_else(|| format!("version {version} is not uploading"))?;
+            let wasm_module_hash = crate::canister::codes::wasm_module_hash(&upload.wasm_module);
+            if wasm_module_hash != upload.expected_hash {
+                return Err(format!("version {version} hash mismatch"));
+            }
+            let Some(upload) = self.uploads.remove(version) else {
+                return Err(format!("version {version} is not uploading"));
+            };
+            self.releases.insert(
+                version.clone(),
+                WasmRelease {
+                    version: version.clone(),
+                    wasm_module_hash,
+                    release_notes: upload.release_notes,
+                    created: now,
+                    wasm_module: upload.wasm_module,
+                },
+            );
+            Ok(())
+        }
+    }
+
+    impl WasmRegistrable<WasmRelease> for WasmReleases {
+        // 查询
+        fn wasm_find_all(&self) -> Vec<&WasmRelease> {
+            self.releases.values().collect()
+        }
+        fn wasm_find(&self, version: &WasmVersion) -> Option<&WasmRelease> {
+            self.releases.get(version)
+        }
+        fn wasm_find_latest(&self) -> Option<&WasmRelease> {
+            self.releases.values().rev().find(|module| module.version.pre.is_none())
+        }
+
+        // 修改
+        fn wasm_upload_start(
+            &mut self,
+            version: WasmVersion,
+            expected_hash: CanisterCodeHash,
+            release_notes: String,
+        ) -> Result<(), String> {
+            self.upload_start_at(version, expected_hash, release_notes, crate::times::now())
+        }
+        fn wasm_upload_chunk(&mut self, version: &WasmVersion, chunk: Vec<u8>) -> Result<u64, String> {
+            let max_module_size = self.max_module_size;
+            let upload = self
+                .uploads
+                .get_mut(version)
+                .ok_or_else(|| format!("version {version} is not uploading"))?;
+            let size = (upload.wasm_module.len() + chunk.len()) as u64;
+            if max_module_size < size {
+                return Err(format!("module size {size} exceeds {max_module_size}"));
+            }
+            upload.wasm_module.extend(chunk);
+            Ok(size)
+        }
+        fn wasm_upload_finish(&mut self, version: &WasmVersion) -> Result<(), String> {
+            self.upload_finish_at(version, crate::times::now())
+        }
+        fn wasm_upload_cancel(&mut self, version: &WasmVersion) -> bool {
+            self.uploads.remove(version).is_some()
+        }
+        fn wasm_delete(&mut self, version: &WasmVersion, in_use: &[&CanisterCodeHash]) -> Result<WasmRelease, String> {
+            let release = self
+                .releases
+                .get(version)
+                .ok_or_else(|| format!("version {version} not found"))?;
+            if in_use.contains(&&release.wasm_module_hash) {
+                return Err(format!("version {version} is in use"));
+            }
+            self.releases
+                .remove(version)
+                .ok_or_else(|| format!("version {version} not found"))
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::{WasmReleases, WasmVersion};
+        use crate::{functions::types::WasmRegistrable, types::TimestampNanos};
+
+        fn version(value: &str) -> WasmVersion {
+            value.parse().unwrap()
+        }
+
+        #[test]
+        fn orders_semantic_versions() {
+            assert!(version("1.10.0") > version("1.9.9"));
+            assert!(version("2.0.0-rc.1") < version("2.0.0"));
+            assert_eq!(version("2.0.0-rc.1").to_string(), "2.0.0-rc.1");
+            assert!("1.0".parse::<WasmVersion>().is_err());
+            assert!("1.0.0-".parse::<WasmVersion>().is_err());
+            assert!("1.0.0-rc..1".parse::<WasmVersion>().is_err());
+            assert!("1.0.0+".parse::<WasmVersion>().is_err());
+
+            // 预发布标识符逐个比较
+            assert!(version("1.0.0-rc.2") < version("1.0.0-rc.10"));
+            assert!(version("1.0.0-alpha") < version("1.0.0-alpha.1"));
+            assert!(version("1.0.0-alpha.1") < version("1.0.0-alpha.beta"));
+            assert!(version("1.0.0-beta.11") < version("1.0.0-rc.1"));
+
+            // 构建信息被忽略
+            assert_eq!(version("1.0.0-rc.1+build.5"), version("1.0.0-rc.1"));
+            assert_eq!(version("1.0.0+20260101").to_string(), "1.0.0");
+
+            let decoded: WasmVersion = candid::decode_one(&candid::encode_one(version("1.2.3")).unwrap()).unwrap();
+            assert_eq!(decoded, version("1.2.3"));
+        }
+
+        #[test]
+        fn verifies_hash_before_registering_version() {
+            let now = TimestampNanos::from(0);
+            let wasm = b"\0asm\x01\0\0\0".to_vec();
+            let hash = crate::canister::codes::wasm_module_hash(&wasm);
+            let mut modules = WasmReleases::default();
+
+            modules
+                .upload_start_at(version("1.0.0"), hash.clone(), "first".to_string(), now)
+                .unwrap();
+            modules
+                .wasm_upload_chunk(&version("1.0.0"), wasm[..4].to_vec())
+                .unwrap();
+            assert!(modules.upload_finish_at(&version("1.0.0"), now).is_err());
+            assert_eq!(modules.wasm_upload_chunk(&version("1.0.0"), wasm[4..].to_vec()), Ok(8));
+            modules.upload_finish_at(&version("1.0.0"), now).unwrap();
+            assert!(
+                modules
+                    .upload_start_at(version("1.0.0"), hash.clone(), String::new(), now)
+                    .is_err()
+            );
+
+            modules
+                .upload_start_at(version("1.1.0-beta"), hash, String::new(), now)
+                .unwrap();
+            modules.upload_finish_at(&version("1.1.0-beta"), now).unwrap_err();
+            assert!(modules.wasm_upload_cancel(&version("1.1.0-beta")));
+
+            let hash = modules.wasm_find(&version("1.0.0")).unwrap().wasm_module_hash.clone();
+            assert!(modules.wasm_delete(&version("1.0.0"), &[&hash]).is_err());
+            assert!(modules.wasm_delete(&version("9.0.0"), &[]).is_err());
+
+            let latest = modules.wasm_find_latest().unwrap();
+            assert_eq!(latest.info().size, 8);
+            assert_eq!(latest.release_notes, "first");
+
+            let bytes = crate::functions::stable::to_bytes(&modules).unwrap();
+            let restored: WasmReleases = crate::functions::stable::from_bytes(&bytes).unwrap();
+            assert_eq!(restored.wasm_find(&version("1.0.0")).unwrap().wasm_module, wasm);
+        }
+    }
+}